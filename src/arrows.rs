@@ -1,15 +1,18 @@
+use crate::config::RhythmConfig;
 use crate::consts::*;
+use crate::score::{Judgement, ScoreResource};
 use crate::time::ControlledTime;
 use crate::types::*;
-use crate::ScoreResource;
 use bevy::prelude::*;
+use serde_derive::{Deserialize, Serialize};
 
 /// Keeps the textures and materials for Arrows
-struct ArrowMaterialResource {
+pub struct ArrowMaterialResource {
     red_texture: Handle<ColorMaterial>,
     blue_texture: Handle<ColorMaterial>,
     green_texture: Handle<ColorMaterial>,
     border_texture: Handle<ColorMaterial>,
+    hold_texture: Handle<ColorMaterial>,
 }
 impl FromWorld for ArrowMaterialResource {
     fn from_world(world: &mut World) -> Self {
@@ -17,28 +20,55 @@ impl FromWorld for ArrowMaterialResource {
 
         let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
         let asset_server = world.get_resource::<AssetServer>().unwrap();
+        let paths = &world.get_resource::<RhythmConfig>().unwrap().assets;
 
-        let red_handle = asset_server.load("images/arrow_red.png");
-        let blue_handle = asset_server.load("images/arrow_blue.png");
-        let green_handle = asset_server.load("images/arrow_green.png");
-        let border_handle = asset_server.load("images/arrow_border.png");
+        let red_handle = asset_server.load(paths.arrow_red.as_str());
+        let blue_handle = asset_server.load(paths.arrow_blue.as_str());
+        let green_handle = asset_server.load(paths.arrow_green.as_str());
+        let border_handle = asset_server.load(paths.arrow_border.as_str());
         ArrowMaterialResource {
             red_texture: materials.add(red_handle.into()),
             blue_texture: materials.add(blue_handle.into()),
             green_texture: materials.add(green_handle.into()),
             border_texture: materials.add(border_handle.into()),
+            hold_texture: materials.add(Color::rgba(1., 1., 1., 0.4).into()),
         }
     }
 }
+impl ArrowMaterialResource {
+    /// Returns the material for an arrow with the given speed
+    pub fn speed_material(&self, speed: Speed) -> Handle<ColorMaterial> {
+        match speed {
+            Speed::Slow => self.red_texture.clone(),
+            Speed::Medium => self.blue_texture.clone(),
+            Speed::Fast => self.green_texture.clone(),
+        }
+    }
 
-struct TargetArrow;
-fn setup_target_arrows(mut commands: Commands, materials: Res<ArrowMaterialResource>) {
-    use Directions::*;
-    let directions = [Up, Down, Left, Right];
+    /// Returns the material for the border of an arrow
+    pub fn border_material(&self) -> Handle<ColorMaterial> {
+        self.border_texture.clone()
+    }
+
+    /// Returns the material for the tail of a hold note
+    pub fn hold_material(&self) -> Handle<ColorMaterial> {
+        self.hold_texture.clone()
+    }
+}
 
-    for direction in directions.iter() {
-        let mut transform =
-            Transform::from_translation(Vec3::new(TARGET_POSITION, direction.y(), 1.));
+struct TargetArrow;
+fn setup_target_arrows(
+    mut commands: Commands,
+    materials: Res<ArrowMaterialResource>,
+    config: Res<RhythmConfig>,
+) {
+    let layout = &config.layout;
+    for direction in Directions::ALL.iter() {
+        let mut transform = Transform::from_translation(Vec3::new(
+            layout.target_position,
+            layout.lane_y(*direction),
+            1.,
+        ));
         transform.rotate(Quat::from_rotation_z(direction.rotation()));
         commands
             .spawn_bundle(SpriteBundle {
@@ -51,73 +81,164 @@ fn setup_target_arrows(mut commands: Commands, materials: Res<ArrowMaterialResou
     }
 }
 
-/// Actual component that goes on the sprites
+/// Actual component that goes on the sprites, which are added by `ArrowSpritesPlugin`
 struct Arrow {
     speed: Speed,
     direction: Directions,
+    click_time: f64,
+    /// Pixels the arrow moves each second
+    velocity: f32,
+    /// Seconds before or after the click time the arrow can be clicked
+    hit_window: f64,
+    /// Time the arrow leaves the screen and is missed
+    miss_time: f64,
+    /// Time the lane can be released, for hold notes
+    hold_end: Option<f64>,
+    /// Time a hold note was pressed at, while it's being held
+    held_since: Option<f64>,
 }
+impl Arrow {
+    /// Returns how far past the target the arrow is at a time of the song
+    fn distance(&self, secs: f64) -> f32 {
+        ((secs - self.click_time) * self.velocity as f64) as f32
+    }
+
+    /// Returns how early or late a press is, from -1 to 1 inside the clicking threshold
+    fn offset(&self, secs: f64) -> f32 {
+        ((secs - self.click_time) / self.hit_window) as f32
+    }
+
+    /// Returns the length in pixels of the part of a hold note's tail that's left to hold
+    fn tail_length(&self, secs: f64) -> f32 {
+        let start = match self.held_since {
+            Some(_) => secs.max(self.click_time),
+            None => self.click_time,
+        };
+        let end = self.hold_end.unwrap_or(self.click_time);
+        ((end - start).max(0.) * self.velocity as f64) as f32
+    }
+}
+
+/// Tail of a hold note, a child of its arrow
+struct HoldTail;
 
 /// Spawns arrows
 fn spawn_arrows(
     mut commands: Commands,
     mut song_config: ResMut<SongConfig>,
-    materials: Res<ArrowMaterialResource>,
     time: Res<ControlledTime>,
+    config: Res<RhythmConfig>,
 ) {
-    // We get the current time since startup (secs) and the time since the last iteration (secs_last),
-    // this way we check if any arrows should spawn in this window
-
     // Song starts 3 seconds after start, so we subtract 3 seconds
-    let secs = time.seconds_since_startup() - 3.;
-    let secs_last = secs - time.delta_seconds_f64();
+    let secs = time.seconds_since_startup() - SONG_START_DELAY;
+    let layout = &config.layout;
 
-    // Counter of how many arrows we need to spawn and remove from the list
-    let mut remove_counter = 0;
-    for arrow in &song_config.arrows {
-        // List is ordered, so we can just check until an item fails
-        // Check if arrow should be spawned at any point between last frame and this frame
-        if secs_last < arrow.spawn_time && arrow.spawn_time < secs {
-            remove_counter += 1;
+    // Arrows that should have spawned since the last frame spawn now, however long the frame was.
+    // Faster arrows spawn later, so the whole list is checked. The spawned ones are removed from it
+    song_config.arrows.retain(|arrow| {
+        if arrow.spawn_time(layout) >= secs {
+            return true;
+        }
+        let velocity = layout.speed(arrow.speed);
+        commands.spawn().insert(Arrow {
+            speed: arrow.speed,
+            direction: arrow.direction,
+            click_time: arrow.click_time,
+            velocity,
+            hit_window: (layout.threshold / velocity) as f64,
+            miss_time: arrow.click_time
+                + ((layout.despawn_position - layout.target_position) / velocity) as f64,
+            hold_end: arrow.hold_end,
+            held_since: None,
+        });
+        false
+    });
+}
 
+/// Gives the arrows that just spawned their sprite
+fn add_arrow_sprites(
+    mut commands: Commands,
+    query: Query<(Entity, &Arrow), Added<Arrow>>,
+    materials: Res<ArrowMaterialResource>,
+    config: Res<RhythmConfig>,
+) {
+    let layout = &config.layout;
+    for (entity, arrow) in query.iter() {
+        let mut transform = Transform::from_translation(Vec3::new(
+            layout.spawn_position,
+            layout.lane_y(arrow.direction),
+            1.,
+        ));
+        // Rotate the arrow acording to direction
+        transform.rotate(Quat::from_rotation_z(arrow.direction.rotation()));
+        commands.entity(entity).insert_bundle(SpriteBundle {
             // Get the correct material according to speed
-            let material = match arrow.speed {
-                Speed::Slow => materials.red_texture.clone(),
-                Speed::Medium => materials.blue_texture.clone(),
-                Speed::Fast => materials.green_texture.clone(),
-            };
+            material: materials.speed_material(arrow.speed),
+            sprite: Sprite::new(Vec2::new(140., 140.)),
+            transform,
+            ..Default::default()
+        });
 
-            let mut transform =
-                Transform::from_translation(Vec3::new(SPAWN_POSITION, arrow.direction.y(), 1.));
-            // Rotate the arrow acording to direction
-            transform.rotate(Quat::from_rotation_z(arrow.direction.rotation()));
-            commands
-                .spawn_bundle(SpriteBundle {
-                    material,
-                    sprite: Sprite::new(Vec2::new(140., 140.)),
-                    transform,
-                    ..Default::default()
-                })
-                .insert(Arrow {
-                    speed: arrow.speed,
-                    direction: arrow.direction,
-                });
-        } else {
-            break;
+        if arrow.hold_end.is_some() {
+            commands.entity(entity).with_children(|parent| {
+                parent
+                    .spawn_bundle(SpriteBundle {
+                        material: materials.hold_material(),
+                        // Its length is set every frame by `update_hold_tails`
+                        sprite: Sprite {
+                            resize_mode: SpriteResizeMode::Manual,
+                            ..Sprite::new(Vec2::new(0., 60.))
+                        },
+                        visible: Visible {
+                            is_transparent: true,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .insert(HoldTail);
+            });
         }
     }
+}
 
-    // Remove the arrows we have spawned from the list
-    for _ in 0..remove_counter {
-        song_config.arrows.remove(0);
+/// Stretches the tails of hold notes behind their arrows, shortening them while they're held
+fn update_hold_tails(
+    time: Res<ControlledTime>,
+    arrows: Query<&Arrow>,
+    mut tails: Query<(&Parent, &mut Sprite, &mut Transform), With<HoldTail>>,
+) {
+    let secs = time.seconds_since_startup() - SONG_START_DELAY;
+    for (parent, mut sprite, mut transform) in tails.iter_mut() {
+        if let Ok(arrow) = arrows.get(parent.0) {
+            let length = arrow.tail_length(secs);
+            sprite.size.x = length;
+            // Undo the rotation of the arrow, so the tail always points back along the lane
+            let rotation = Quat::from_rotation_z(arrow.direction.rotation()).inverse();
+            transform.rotation = rotation;
+            transform.translation = rotation * Vec3::new(-length / 2., 0., -0.1);
+        }
     }
 }
 
 /// Moves the arrows forward
-fn move_arrows(time: Res<ControlledTime>, mut query: Query<(&mut Transform, &Arrow)>) {
+fn move_arrows(
+    time: Res<ControlledTime>,
+    config: Res<RhythmConfig>,
+    mut query: Query<(&mut Transform, &Arrow)>,
+) {
+    let secs = time.seconds_since_startup() - SONG_START_DELAY;
+    let layout = &config.layout;
     for (mut transform, arrow) in query.iter_mut() {
-        transform.translation.x += time.delta_seconds() * arrow.speed.value();
+        // Place the arrow from the song time, so it's where the judgement expects it.
+        // Hold notes being held stay on the target
+        let distance = match arrow.held_since {
+            Some(_) => arrow.distance(secs).min(0.),
+            None => arrow.distance(secs),
+        };
+        transform.translation.x = layout.target_position + distance;
 
-        let distance_after_target = transform.translation.x - (TARGET_POSITION + THRESHOLD);
+        let distance_after_target =
+            transform.translation.x - (layout.target_position + layout.threshold);
         if distance_after_target >= 0.02 {
             // Move the arrow down if it's past the target
             transform.translation.y -= time.delta_seconds() * distance_after_target * 2.;
@@ -139,52 +260,257 @@ pub struct CorrectArrowEvent {
     pub points: usize,
 }
 
-/// Despawns arrows when they reach the end if the correct button is clicked
+/// Sent for every hit or missed arrow
+pub struct JudgementEvent {
+    pub judgement: Judgement,
+    /// Combo after this judgement
+    pub combo: usize,
+}
+
+/// Where the lane presses of a song come from
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum PlayMode {
+    /// The player presses the keys
+    #[default]
+    Play,
+    /// The presses come from a replay
+    Watch,
+    /// Every note is pressed perfectly
+    Autoplay,
+}
+/// State to go to once the song finishes instead of `GameStates::after_song`, for songs started
+/// from somewhere they should return to. Cleared when the song finishes
+#[derive(Default)]
+pub struct ReturnState(pub Option<AppState>);
+
+/// A lane key being pressed or released, at a time of the song
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct LaneInput {
+    /// Seconds since the song started
+    pub time: f64,
+    pub direction: Directions,
+    pub pressed: bool,
+}
+
+/// Sends the lane keys pressed by the player
+fn keyboard_lane_input(
+    time: Res<ControlledTime>,
+    mode: Res<PlayMode>,
+    keyboard_input: Res<Input<KeyCode>>,
+    config: Res<RhythmConfig>,
+    mut lane_inputs: EventWriter<LaneInput>,
+) {
+    if *mode != PlayMode::Play {
+        return;
+    }
+
+    let secs = time.seconds_since_startup() - SONG_START_DELAY;
+    for direction in Directions::ALL.iter() {
+        for &pressed in [true, false].iter() {
+            let changed = if pressed {
+                config.keys.just_pressed(*direction, &keyboard_input)
+            } else {
+                config.keys.just_released(*direction, &keyboard_input)
+            };
+            if changed {
+                lane_inputs.send(LaneInput {
+                    time: secs,
+                    direction: *direction,
+                    pressed,
+                });
+            }
+        }
+    }
+}
+
+/// Despawns arrows when they reach the end if the correct lane is pressed.
+/// Hold notes are judged again when they're released, and despawned then
 fn despawn_arrows(
     mut commands: Commands,
-    query: Query<(Entity, &Transform, &Arrow)>,
-    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<(Entity, &mut Arrow)>,
+    time: Res<ControlledTime>,
+    mut lane_inputs: EventReader<LaneInput>,
     mut score: ResMut<ScoreResource>,
     mut correct_arrow_events: EventWriter<CorrectArrowEvent>,
+    mut judgement_events: EventWriter<JudgementEvent>,
 ) {
-    for (entity, transform, arrow) in query.iter() {
-        let pos = transform.translation.x;
+    let secs = time.seconds_since_startup() - SONG_START_DELAY;
+    let inputs: Vec<LaneInput> = lane_inputs.iter().copied().collect();
 
-        // Check if arrow is inside clicking threshold
-        if (TARGET_POSITION - THRESHOLD..=TARGET_POSITION + THRESHOLD).contains(&pos)
-            && arrow.direction.key_just_pressed(&keyboard_input)
-        {
-            commands.entity(entity).despawn();
+    // Judge with the time of each input instead of the frame, so a replay scores the same.
+    // Hits are `Some(offset)` and misses `None`, kept with the time they happened at
+    // and whether they're the last judgement of the arrow
+    let mut judged: Vec<(f64, Entity, Directions, Option<f32>, bool)> = vec![];
+    for (entity, mut arrow) in query.iter_mut() {
+        let direction = arrow.direction;
+        if arrow.held_since.is_none() {
+            // Check if a press was inside the clicking threshold
+            let hit = inputs.iter().find(|input| {
+                input.pressed
+                    && input.direction == direction
+                    && arrow.offset(input.time).abs() <= 1.
+            });
 
-            let points = score.increase_correct(TARGET_POSITION - pos);
+            match hit {
+                Some(press) => {
+                    let is_tap = arrow.hold_end.is_none();
+                    judged.push((
+                        press.time,
+                        entity,
+                        direction,
+                        Some(arrow.offset(press.time)),
+                        is_tap,
+                    ));
+                    if !is_tap {
+                        arrow.held_since = Some(press.time);
+                    }
+                }
+                // Arrows are missed once they leave the screen, and hold notes miss their end too
+                None if secs >= arrow.miss_time => {
+                    if arrow.hold_end.is_some() {
+                        judged.push((arrow.miss_time, entity, direction, None, false));
+                    }
+                    judged.push((arrow.miss_time, entity, direction, None, true));
+                }
+                None => {}
+            }
+        }
 
-            correct_arrow_events.send(CorrectArrowEvent {
-                direction: arrow.direction,
-                points,
+        if let (Some(held_since), Some(hold_end)) = (arrow.held_since, arrow.hold_end) {
+            let release = inputs.iter().find(|input| {
+                !input.pressed && input.direction == direction && input.time >= held_since
             });
+            match release {
+                // Letting go before the end is a miss
+                Some(release) if release.time < hold_end - arrow.hit_window => {
+                    judged.push((release.time, entity, direction, None, true))
+                }
+                // Holding until the end is a perfect hit
+                Some(release) => judged.push((
+                    release.time.min(hold_end),
+                    entity,
+                    direction,
+                    Some(0.),
+                    true,
+                )),
+                None if secs >= hold_end => {
+                    judged.push((hold_end, entity, direction, Some(0.), true))
+                }
+                None => {}
+            }
         }
+    }
 
-        // Despawn arrows after they leave the screen
-        if pos >= 2. * TARGET_POSITION {
-            commands.entity(entity).despawn();
-            score.increase_fails();
+    // Keep the combo the same no matter how the judgements were split between frames
+    judged.sort_by(|a, b| a.0.total_cmp(&b.0));
+    for (_, entity, direction, hit, is_last) in judged {
+        if is_last {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        match hit {
+            Some(offset) => {
+                let points = score.increase_correct(offset);
+
+                correct_arrow_events.send(CorrectArrowEvent { direction, points });
+                judgement_events.send(JudgementEvent {
+                    judgement: Judgement::from_points(points),
+                    combo: score.combo(),
+                });
+            }
+            None => {
+                score.increase_fails();
+
+                judgement_events.send(JudgementEvent {
+                    judgement: Judgement::Miss,
+                    combo: 0,
+                });
+            }
         }
     }
 }
 
+/// Shows the results once the last note is done
+fn finish_song(
+    time: Res<ControlledTime>,
+    song_config: Res<SongConfig>,
+    query: Query<&Arrow>,
+    config: Res<RhythmConfig>,
+    mut return_state: ResMut<ReturnState>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let secs = time.seconds_since_startup() - SONG_START_DELAY;
+    if song_config.arrows.is_empty()
+        && query.iter().next().is_none()
+        && secs > song_config.end_time + SONG_END_DELAY
+    {
+        let next = return_state
+            .0
+            .take()
+            .unwrap_or_else(|| config.states.after_song.clone());
+        state
+            .set(next)
+            .expect("Couldn't switch state after the song");
+    }
+}
+
+fn reset_score(mut score: ResMut<ScoreResource>) {
+    *score = ScoreResource::default();
+}
+
+/// Despawns the arrows left when leaving a song
+fn despawn_game_arrows(mut commands: Commands, query: Query<Entity, With<Arrow>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn despawn_target_arrows(mut commands: Commands, query: Query<Entity, With<TargetArrow>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Rules of the game: spawning and judging arrows, and scoring. Runs without a window
 pub struct ArrowsPlugin;
 impl Plugin for ArrowsPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.init_resource::<ArrowMaterialResource>()
+        app.init_resource::<ScoreResource>()
             .add_event::<CorrectArrowEvent>()
+            .add_event::<JudgementEvent>()
+            .add_event::<LaneInput>()
+            .init_resource::<PlayMode>()
+            .init_resource::<ReturnState>()
+            .add_system_set(SystemSet::on_enter(AppState::Game).with_system(reset_score.system()))
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(spawn_arrows.system())
+                    .with_system(keyboard_lane_input.system().label("lane_input"))
+                    .with_system(despawn_arrows.system().after("lane_input"))
+                    .with_system(finish_song.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Game).with_system(despawn_game_arrows.system()),
+            );
+    }
+}
+
+/// Draws the arrows and the targets they have to be clicked at
+pub struct ArrowSpritesPlugin;
+impl Plugin for ArrowSpritesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ArrowMaterialResource>()
             .add_system_set(
                 SystemSet::on_enter(AppState::Game).with_system(setup_target_arrows.system()),
             )
             .add_system_set(
                 SystemSet::on_update(AppState::Game)
-                    .with_system(spawn_arrows.system())
+                    .with_system(add_arrow_sprites.system())
                     .with_system(move_arrows.system())
-                    .with_system(despawn_arrows.system()),
+                    .with_system(update_hold_tails.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Game).with_system(despawn_target_arrows.system()),
             );
     }
 }
@@ -1,21 +1,66 @@
 use crate::consts::*;
+use crate::library;
+use crate::playback::SongPlayer;
 use crate::time::ControlledTime;
 use crate::types::SongConfig;
 use bevy::prelude::*;
 
-fn start_song(audio: Res<Audio>, time: Res<ControlledTime>, config: Res<SongConfig>) {
+fn stop_song(mut player: NonSendMut<SongPlayer>) {
+    player.stop();
+}
+
+/// Audio of the song being played
+#[derive(Default)]
+struct SongAudio(Handle<AudioSource>);
+
+/// Loads the song's audio from next to its chart, which the asset server can't do for zipped packs.
+/// If it can't be read, the song is played without audio
+fn load_song_audio(
+    config: Res<SongConfig>,
+    mut audio_sources: ResMut<Assets<AudioSource>>,
+    mut song_audio: ResMut<SongAudio>,
+) {
+    song_audio.0 = match library::read_file(&config.song_audio) {
+        Ok(bytes) => audio_sources.add(AudioSource {
+            bytes: bytes.into(),
+        }),
+        Err(error) => {
+            warn!(
+                "Couldn't read assets/songs/{}, playing without audio: {}",
+                config.song_audio, error
+            );
+            Handle::default()
+        }
+    };
+}
+
+fn start_song(
+    mut player: NonSendMut<SongPlayer>,
+    audio_sources: Res<Assets<AudioSource>>,
+    time: Res<ControlledTime>,
+    song_audio: Res<SongAudio>,
+) {
     // Song starts 3 seconds after real time
     let secs = time.seconds_since_startup();
-    let secs_last = secs - time.delta_seconds_f64();
 
-    if secs_last <= 3. && 3. <= secs {
-        audio.play(config.song_audio.clone());
+    if SONG_START_DELAY <= secs && !player.is_playing() {
+        // The song gets played through the `SongPlayer` so it follows the volume settings
+        if let Some(audio) = audio_sources.get(&song_audio.0) {
+            player.play_audio(audio);
+        }
     }
 }
 
 pub struct AudioPlugin;
 impl Plugin for AudioPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system_set(SystemSet::on_update(AppState::Game).with_system(start_song.system()));
+        app.init_resource::<SongAudio>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Game)
+                    .with_system(stop_song.system())
+                    .with_system(load_song_audio.system()),
+            )
+            .add_system_set(SystemSet::on_update(AppState::Game).with_system(start_song.system()))
+            .add_system_set(SystemSet::on_exit(AppState::Game).with_system(stop_song.system()));
     }
 }
@@ -0,0 +1,68 @@
+use crate::arrows::{LaneInput, PlayMode};
+use crate::consts::*;
+use crate::time::ControlledTime;
+use crate::types::SongConfig;
+use bevy::prelude::*;
+
+/// Lane presses and releases that play the current song perfectly
+#[derive(Default)]
+struct Autoplay {
+    inputs: Vec<LaneInput>,
+    /// Index of the next input to send
+    next: usize,
+}
+
+/// Presses every note of the song right on its click time, holding hold notes until their end
+fn setup_autoplay(song_config: Res<SongConfig>, mut autoplay: ResMut<Autoplay>) {
+    let mut inputs = vec![];
+    for arrow in song_config.arrows.iter() {
+        inputs.push(LaneInput {
+            time: arrow.click_time,
+            direction: arrow.direction,
+            pressed: true,
+        });
+        inputs.push(LaneInput {
+            time: arrow.hold_end.unwrap_or(arrow.click_time + AUTOPLAY_HOLD),
+            direction: arrow.direction,
+            pressed: false,
+        });
+    }
+    inputs.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    *autoplay = Autoplay { inputs, next: 0 };
+}
+
+/// Sends the autoplay inputs once the song reaches them
+fn autoplay_lane_input(
+    time: Res<ControlledTime>,
+    mode: Res<PlayMode>,
+    mut autoplay: ResMut<Autoplay>,
+    mut lane_inputs: EventWriter<LaneInput>,
+) {
+    if *mode != PlayMode::Autoplay {
+        return;
+    }
+
+    let secs = time.seconds_since_startup() - SONG_START_DELAY;
+    while let Some(input) = autoplay.inputs.get(autoplay.next).copied() {
+        if input.time > secs {
+            break;
+        }
+        lane_inputs.send(input);
+        autoplay.next += 1;
+    }
+}
+
+pub struct AutoplayPlugin;
+impl Plugin for AutoplayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Autoplay>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Game).with_system(setup_autoplay.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(autoplay_lane_input.system().label("lane_input")),
+            );
+    }
+}
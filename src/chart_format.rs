@@ -0,0 +1,181 @@
+use crate::cli::charts_or_all;
+use crate::consts::*;
+use crate::import;
+use crate::library;
+use crate::types::{write_config, SongConfigToml};
+use std::{convert::TryFrom, process};
+use toml::{value::Table, Value};
+
+// Charts have a `format_version`, and charts of older versions are migrated when they're loaded.
+// Migrations work on the toml table, since older charts may not parse as the current format.
+// Charts written before there were versions are version 0.
+
+/// Changes a chart into the next version, indexed by the version they change from
+const MIGRATIONS: [fn(&mut Table); CHART_FORMAT_VERSION as usize] = [from_unversioned, from_v1];
+
+/// Version 1 has the same fields as the charts from before versions
+fn from_unversioned(_chart: &mut Table) {}
+
+/// Version 2 adds hold notes, which notes of older charts aren't
+fn from_v1(_chart: &mut Table) {}
+
+/// Parses the text of a chart of any version up to the current one, migrating it.
+/// Returns the chart and the version it was written in
+pub fn parse(text: &str) -> Result<(SongConfigToml, u32), String> {
+    let mut chart: Table = toml::from_str(text).map_err(|error| error.to_string())?;
+    let version = match chart.get("format_version") {
+        None => 0,
+        Some(Value::Integer(version)) if *version >= 0 => *version,
+        Some(_) => return Err("format_version has to be a whole number of 0 or more".to_string()),
+    };
+    let too_new = || {
+        format!(
+            "The chart is format version {}, but this version of the game only reads up to version {}",
+            version, CHART_FORMAT_VERSION
+        )
+    };
+    let version = u32::try_from(version).map_err(|_| too_new())?;
+    if version > CHART_FORMAT_VERSION {
+        return Err(too_new());
+    }
+
+    for migration in MIGRATIONS[version as usize..].iter() {
+        migration(&mut chart);
+    }
+    chart.insert(
+        "format_version".to_string(),
+        Value::Integer(CHART_FORMAT_VERSION as i64),
+    );
+
+    let config = Value::Table(chart)
+        .try_into()
+        .map_err(|error| error.to_string())?;
+    Ok((config, version))
+}
+
+const USAGE: &str = "Usage: bevy_rhythm migrate [chart...]
+
+Rewrites chart files in `assets/songs` that were written in an older version of the chart format.
+Charts are migrated when they're loaded anyway, this only updates the files.
+Without charts, migrates every chart in `assets/songs`.";
+
+/// Migrates chart files from the command line
+pub fn run_cli(args: &[String]) {
+    let mut failed = false;
+    let mut migrated = 0;
+    for chart in charts_or_all(args, USAGE) {
+        let path = format!("{}.toml", chart);
+        // Charts imported from other games don't have a file to rewrite
+        if import::is_imported(&chart) {
+            continue;
+        }
+        let bytes = match library::read_file(&path) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                println!("{}: Couldn't read chart: {}", chart, error);
+                failed = true;
+                continue;
+            }
+        };
+        let parsed = String::from_utf8(bytes)
+            .map_err(|error| error.to_string())
+            .and_then(|text| parse(&text));
+        let (config, version) = match parsed {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                println!("{}: Couldn't parse chart: {}", chart, error);
+                failed = true;
+                continue;
+            }
+        };
+        if version == CHART_FORMAT_VERSION {
+            continue;
+        }
+        if library::is_archived(&path) {
+            println!("{}: Charts in zipped packs can't be rewritten", chart);
+            failed = true;
+            continue;
+        }
+
+        write_config(&path, &config);
+        migrated += 1;
+        println!(
+            "{}: Migrated from version {} to {}",
+            chart, version, CHART_FORMAT_VERSION
+        );
+    }
+
+    println!("Migrated {} charts", migrated);
+    if failed {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHART: &str = r#"
+name = "Versions"
+filename = "song.ogg"
+
+[[arrows]]
+click_time = 1.0
+speed = "Medium"
+direction = "Up"
+"#;
+
+    fn with_version(version: i64) -> String {
+        format!("format_version = {}\n{}", version, CHART)
+    }
+
+    #[test]
+    fn unversioned_charts_are_migrated() {
+        let (config, version) = parse(CHART).expect("Couldn't parse chart");
+        assert_eq!(version, 0);
+        assert_eq!(config.format_version, CHART_FORMAT_VERSION);
+        assert_eq!(config.arrows.len(), 1);
+        assert_eq!(config.arrows[0].hold_end, None);
+    }
+
+    #[test]
+    fn current_charts_are_read_as_they_are() {
+        let text = with_version(CHART_FORMAT_VERSION as i64);
+        let (config, version) = parse(&text).expect("Couldn't parse chart");
+        assert_eq!(version, CHART_FORMAT_VERSION);
+        assert_eq!(config.name, "Versions");
+    }
+
+    #[test]
+    fn charts_of_newer_versions_are_rejected() {
+        let text = with_version(CHART_FORMAT_VERSION as i64 + 1);
+        assert_eq!(
+            parse(&text).unwrap_err(),
+            format!(
+                "The chart is format version {}, but this version of the game only reads up to version {}",
+                CHART_FORMAT_VERSION + 1,
+                CHART_FORMAT_VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn versions_too_large_for_a_u32_are_newer() {
+        let version = u32::MAX as i64 + 1;
+        assert_eq!(
+            parse(&with_version(version)).unwrap_err(),
+            format!(
+                "The chart is format version {}, but this version of the game only reads up to version {}",
+                version, CHART_FORMAT_VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn negative_versions_are_rejected() {
+        assert_eq!(
+            parse(&with_version(-1)).unwrap_err(),
+            "format_version has to be a whole number of 0 or more"
+        );
+    }
+}
@@ -0,0 +1,30 @@
+use crate::library;
+use std::process;
+
+// Helpers shared by the subcommands in `main.rs`, which each have their own `USAGE` text.
+
+/// Prints what went wrong and how the subcommand is used, and exits
+pub fn exit_with_usage(error: &str, usage: &str) -> ! {
+    eprintln!("{}\n\n{}", error, usage);
+    process::exit(1);
+}
+
+/// Prints what went wrong and exits
+pub fn exit_with_error(error: &str) -> ! {
+    eprintln!("{}", error);
+    process::exit(1);
+}
+
+/// Returns the charts given to a subcommand without their extension, or every chart if there are none.
+/// Exits if there are options, for subcommands that don't take any
+pub fn charts_or_all(args: &[String], usage: &str) -> Vec<String> {
+    if let Some(flag) = args.iter().find(|arg| arg.starts_with("--")) {
+        exit_with_usage(&format!("Unknown option {}", flag), usage);
+    }
+    if args.is_empty() {
+        return library::find_charts();
+    }
+    args.iter()
+        .map(|chart| chart.trim_end_matches(".toml").to_string())
+        .collect()
+}
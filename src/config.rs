@@ -0,0 +1,161 @@
+use crate::consts::*;
+use crate::types::{Directions, Speed};
+use bevy::prelude::*;
+
+/// Settings for apps that embed the game. Insert it before adding `RhythmPlugins` to change them
+#[derive(Clone, Debug, Default)]
+pub struct RhythmConfig {
+    pub layout: Layout,
+    pub assets: AssetPaths,
+    pub states: GameStates,
+    pub keys: KeyBindings,
+}
+
+/// Where the arrows are drawn and how fast they move
+#[derive(Clone, Debug)]
+pub struct Layout {
+    /// X coordinate value at which arrows spawn, should be out of screen
+    pub spawn_position: f32,
+    /// X coordinate value where the arrows should be clicked
+    pub target_position: f32,
+    /// X coordinate value at which arrows that weren't clicked are missed
+    pub despawn_position: f32,
+    /// Margin of error for clicking an arrow
+    pub threshold: f32,
+    /// Speed at which a Slow arrow moves
+    pub base_speed: f32,
+    /// Y coordinate of each lane, in the order of `Directions::ALL`
+    pub lanes: [f32; 4],
+}
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            spawn_position: -400.,
+            target_position: 200.,
+            despawn_position: 400.,
+            threshold: 20.,
+            base_speed: 200.,
+            lanes: [
+                Directions::Up.y(),
+                Directions::Down.y(),
+                Directions::Left.y(),
+                Directions::Right.y(),
+            ],
+        }
+    }
+}
+impl Layout {
+    /// Returns actual speed at which an arrow should move
+    pub fn speed(&self, speed: Speed) -> f32 {
+        self.base_speed * speed.multiplier()
+    }
+
+    /// Returns the seconds an arrow takes from spawning to reaching the target
+    pub fn travel_time(&self, speed: Speed) -> f64 {
+        ((self.target_position - self.spawn_position) / self.speed(speed)) as f64
+    }
+
+    pub fn lane_y(&self, direction: Directions) -> f32 {
+        self.lanes[direction as usize]
+    }
+}
+
+/// Files loaded through the asset server, relative to the `assets` folder
+#[derive(Clone, Debug)]
+pub struct AssetPaths {
+    pub font: String,
+    pub arrow_red: String,
+    pub arrow_blue: String,
+    pub arrow_green: String,
+    pub arrow_border: String,
+    /// Folder with the files that replace the built in sound effects
+    pub sounds: String,
+}
+impl Default for AssetPaths {
+    fn default() -> Self {
+        Self {
+            font: "fonts/FiraSans-Bold.ttf".to_string(),
+            arrow_red: "images/arrow_red.png".to_string(),
+            arrow_blue: "images/arrow_blue.png".to_string(),
+            arrow_green: "images/arrow_green.png".to_string(),
+            arrow_border: "images/arrow_border.png".to_string(),
+            sounds: "sounds".to_string(),
+        }
+    }
+}
+
+/// States the game goes through
+#[derive(Clone, Debug)]
+pub struct GameStates {
+    /// State the app starts in
+    pub initial: AppState,
+    /// State shown once a song finishes. Plays are only saved when it's `AppState::Results`
+    pub after_song: AppState,
+}
+impl Default for GameStates {
+    fn default() -> Self {
+        Self {
+            initial: AppState::Menu,
+            after_song: AppState::Results,
+        }
+    }
+}
+
+/// Keys that press each lane
+#[derive(Clone, Debug)]
+pub struct KeyBindings {
+    /// Keys of each lane, in the order of `Directions::ALL`
+    pub lanes: [Vec<KeyCode>; 4],
+}
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            lanes: [
+                vec![KeyCode::Up, KeyCode::D],
+                vec![KeyCode::Down, KeyCode::F],
+                vec![KeyCode::Left, KeyCode::J],
+                vec![KeyCode::Right, KeyCode::K],
+            ],
+        }
+    }
+}
+impl KeyBindings {
+    pub fn keys(&self, direction: Directions) -> &[KeyCode] {
+        &self.lanes[direction as usize]
+    }
+
+    /// Checks if a key that corresponds to this direction has been pressed
+    pub fn just_pressed(&self, direction: Directions, input: &Input<KeyCode>) -> bool {
+        self.keys(direction)
+            .iter()
+            .any(|code| input.just_pressed(*code))
+    }
+
+    /// Checks if a key that corresponds to this direction has been released
+    pub fn just_released(&self, direction: Directions, input: &Input<KeyCode>) -> bool {
+        self.keys(direction)
+            .iter()
+            .any(|code| input.just_released(*code))
+    }
+
+    /// Checks if a key that corresponds to this direction is being pressed
+    pub fn pressed(&self, direction: Directions, input: &Input<KeyCode>) -> bool {
+        self.keys(direction).iter().any(|code| input.pressed(*code))
+    }
+}
+
+/// Adds the config if the app didn't insert its own, and the states from it
+pub struct ConfigPlugin;
+impl Plugin for ConfigPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<RhythmConfig>();
+        let initial = app
+            .world()
+            .get_resource::<RhythmConfig>()
+            .expect("Couldn't find RhythmConfig")
+            .states
+            .initial
+            .clone();
+        app.add_state(initial);
+    }
+}
@@ -1,18 +1,69 @@
-/// Speed at which a Slow arrow moves
-pub const BASE_SPEED: f32 = 200.;
-/// X coordinate value at which arrows spawn, should be out of screen
-pub const SPAWN_POSITION: f32 = -400.;
-/// X coordinate value where the arrows should be clicked
-pub const TARGET_POSITION: f32 = 200.;
-/// Margin of error for clicking an arrow
-pub const THRESHOLD: f32 = 20.;
-/// Total distance traveled by an arrow, from spawn to target
-pub const DISTANCE: f32 = TARGET_POSITION - SPAWN_POSITION;
+use crate::types::Speed;
+
+/// Seconds between entering a song and its audio starting
+pub const SONG_START_DELAY: f64 = 3.;
+/// Seconds between the last note of a song and going back to the menu
+pub const SONG_END_DELAY: f64 = 2.;
+/// Seconds autoplay holds a lane down for each note
+pub const AUTOPLAY_HOLD: f64 = 0.08;
+/// Seconds each frame of a headless simulation advances
+pub const SIMULATION_STEP: f64 = 1. / 60.;
+/// Seconds under which notes on the same lane are the same note written twice
+pub const DUPLICATE_MARGIN: f64 = 0.001;
+/// Seconds under which notes are hit together as a chord
+pub const CHORD_MARGIN: f64 = 0.01;
+/// Seconds of the window used to find the peak note density of a chart
+pub const STATS_WINDOW: f64 = 2.;
+/// Seconds under which consecutive presses count towards jacks and streams
+pub const QUICK_PRESS_GAP: f64 = 0.2;
+/// Quick presses in a row that change lanes to count as a stream
+pub const STREAM_LENGTH: usize = 4;
+/// Speed of the notes of charts imported from other games, which don't have one
+pub const IMPORTED_SPEED: Speed = Speed::Medium;
+/// Version of the chart format that charts are written in
+pub const CHART_FORMAT_VERSION: u32 = 2;
+
+/// X coordinate of the left edge of the chart editor timeline
+pub const TIMELINE_START: f32 = -350.;
+/// Default horizontal pixels per second in the chart editor timeline
+pub const TIMELINE_ZOOM: f32 = 150.;
+/// Size of the notes drawn in the chart editor timeline
+pub const TIMELINE_NOTE_SIZE: f32 = 60.;
+/// Y coordinate of the waveform drawn above the chart editor lanes
+pub const WAVEFORM_Y: f32 = 205.;
+/// Y coordinate of the spectrogram drawn below the chart editor lanes
+pub const SPECTROGRAM_Y: f32 = -205.;
+/// Folder where decoded songs and their waveforms are cached
+pub const CACHE_FOLDER: &str = "cache";
+/// Name of the image shown in the song select for songs in their own folder
+pub const BANNER_FILENAME: &str = "banner.png";
+/// Extensions of the audio files that songs and sound effects can use
+pub const AUDIO_EXTENSIONS: [&str; 4] = ["mp3", "ogg", "wav", "flac"];
+
+/// Length of the song preview in the menu, in seconds
+pub const PREVIEW_LENGTH: f64 = 15.;
+/// Seconds the preview takes to fade in and out at each end of the loop
+pub const PREVIEW_LOOP_FADE: f64 = 1.5;
+/// Seconds the preview takes to fade out when it's stopped
+pub const PREVIEW_STOP_FADE: f32 = 0.3;
+
+/// Number of songs shown at once in the song select list
+pub const SONG_SELECT_ROWS: usize = 7;
+/// Pixels scrolled with a touchpad to move the song select list by a row, the height of a row
+pub const SONG_SELECT_SCROLL_PIXELS: f32 = 54.;
+
+/// Combo counts that are a multiple of this play a sound
+pub const COMBO_MILESTONE: usize = 50;
 
 /// States
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum AppState {
     Menu,
     Game,
+    PickSong,
     MakeMap,
+    ExportMap,
+    EditMap,
+    Settings,
+    Results,
 }
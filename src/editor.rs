@@ -0,0 +1,515 @@
+use crate::arrows::{ArrowMaterialResource, PlayMode, ReturnState};
+use crate::consts::*;
+use crate::library;
+use crate::playback::Playback;
+use crate::time::ControlledTime;
+use crate::timeline::*;
+use crate::types::{
+    load_config, parse_config, write_config, ArrowTimeToml, BeatGrid, Directions::*,
+    SongConfigToml, Speed,
+};
+use bevy::{
+    input::{keyboard::KeyCode, Input},
+    prelude::*,
+};
+use std::mem;
+
+/// Chart being edited, with its undo and redo history
+#[derive(Default)]
+pub struct EditorChart {
+    /// File stem of the chart inside `assets/songs`
+    stem: String,
+    config: Option<SongConfigToml>,
+    /// Index of the selected note
+    selected: Option<usize>,
+    undo: Vec<Vec<ArrowTimeToml>>,
+    redo: Vec<Vec<ArrowTimeToml>>,
+    /// True if there are changes that haven't been saved
+    dirty: bool,
+    /// Notes as they were before the current drag started
+    drag: Option<Vec<ArrowTimeToml>>,
+}
+impl EditorChart {
+    /// Loads the chart with the given file stem, keeping the current one if it can't be parsed
+    pub fn load(&mut self, stem: &str) -> Result<(), String> {
+        let config = parse_config(&format!("{}.toml", stem))?;
+        *self = Self {
+            stem: stem.to_string(),
+            config: Some(config),
+            ..Default::default()
+        };
+        Ok(())
+    }
+
+    /// Writes the chart back to the file it was loaded from
+    pub fn save(&mut self) {
+        if let Some(config) = &mut self.config {
+            config
+                .arrows
+                .sort_by(|a, b| a.click_time.total_cmp(&b.click_time));
+            write_config(&format!("{}.toml", self.stem), config);
+            self.selected = None;
+            self.dirty = false;
+        }
+    }
+
+    pub fn beat_grid(&self) -> Option<BeatGrid> {
+        self.config.as_ref().and_then(|config| config.beat_grid())
+    }
+
+    pub fn arrows(&self) -> &[ArrowTimeToml] {
+        self.config
+            .as_ref()
+            .map(|config| config.arrows.as_slice())
+            .unwrap_or(&[])
+    }
+
+    fn arrows_mut(&mut self) -> &mut Vec<ArrowTimeToml> {
+        &mut self.config.as_mut().expect("No chart loaded").arrows
+    }
+
+    /// Saves the current notes so the next change can be undone
+    fn checkpoint(&mut self) {
+        let arrows = self.arrows().to_vec();
+        self.push_undo(arrows);
+    }
+
+    /// Adds a previous state of the notes to the undo history
+    fn push_undo(&mut self, arrows: Vec<ArrowTimeToml>) {
+        self.undo.push(arrows);
+        self.redo.clear();
+        self.dirty = true;
+    }
+
+    fn undo(&mut self) {
+        if let Some(arrows) = self.undo.pop() {
+            let current = mem::replace(self.arrows_mut(), arrows);
+            self.redo.push(current);
+            self.selected = None;
+            self.dirty = true;
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(arrows) = self.redo.pop() {
+            let current = mem::replace(self.arrows_mut(), arrows);
+            self.undo.push(current);
+            self.selected = None;
+            self.dirty = true;
+        }
+    }
+
+    /// Changes the selected note, saving a checkpoint if it's different
+    fn edit_selected(&mut self, edit: impl Fn(&mut ArrowTimeToml)) {
+        if let Some(index) = self.selected {
+            let mut arrow = self.arrows()[index].clone();
+            edit(&mut arrow);
+            if arrow != self.arrows()[index] {
+                self.checkpoint();
+                self.arrows_mut()[index] = arrow;
+            }
+        }
+    }
+}
+
+fn reset_editor_input(mut mouse_input: ResMut<Input<MouseButton>>) {
+    // The click that opened the editor shouldn't add a note
+    mouse_input.reset(MouseButton::Left);
+}
+
+/// Adds, selects, moves and deletes notes with the mouse
+fn edit_with_mouse(
+    mouse_input: Res<Input<MouseButton>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    windows: Res<Windows>,
+    mut chart: ResMut<EditorChart>,
+    view: Res<TimelineView>,
+    mut time: ResMut<ControlledTime>,
+    mut playback: ResMut<Playback>,
+) {
+    let cursor = match cursor_position(&windows) {
+        Some(cursor) => cursor,
+        None => return,
+    };
+
+    // Move the playhead when clicking while holding Shift
+    let shift = keyboard_input.pressed(KeyCode::LShift) || keyboard_input.pressed(KeyCode::RShift);
+    if shift {
+        if mouse_input.just_pressed(MouseButton::Left) {
+            playback.seek(&mut time, view.time(cursor.x));
+        }
+        return;
+    }
+
+    if mouse_input.just_pressed(MouseButton::Left) {
+        match view.note_at(chart.arrows(), cursor) {
+            // Select a note and start dragging it
+            Some(index) => {
+                chart.selected = Some(index);
+                chart.drag = Some(chart.arrows().to_vec());
+            }
+            // Add a new note in the clicked lane
+            None => {
+                if let Some(direction) = lane_at(cursor.y) {
+                    chart.checkpoint();
+                    chart.arrows_mut().push(ArrowTimeToml {
+                        click_time: view.time(cursor.x),
+                        speed: Speed::Slow,
+                        direction,
+                        hold_end: None,
+                    });
+                    chart.selected = Some(chart.arrows().len() - 1);
+                }
+            }
+        }
+    }
+
+    // Move the dragged note to the cursor
+    if mouse_input.pressed(MouseButton::Left) && chart.drag.is_some() {
+        if let (Some(index), Some(direction)) = (chart.selected, lane_at(cursor.y)) {
+            let click_time = view.time(cursor.x);
+            let arrow = &chart.arrows()[index];
+            if arrow.click_time != click_time || arrow.direction != direction {
+                let arrow = &mut chart.arrows_mut()[index];
+                // Hold notes keep their length
+                let moved_by = click_time - arrow.click_time;
+                arrow.hold_end = arrow.hold_end.map(|hold_end| hold_end + moved_by);
+                arrow.click_time = click_time;
+                arrow.direction = direction;
+            }
+        }
+    }
+
+    // Finish the drag, only keeping it in the history if the note moved
+    if mouse_input.just_released(MouseButton::Left) && chart.drag.is_some() {
+        if let Some(before) = chart.drag.take() {
+            if before != chart.arrows() {
+                chart.push_undo(before);
+            }
+        }
+    }
+
+    if mouse_input.just_pressed(MouseButton::Right) {
+        if let Some(index) = view.note_at(chart.arrows(), cursor) {
+            chart.checkpoint();
+            chart.arrows_mut().remove(index);
+            chart.selected = None;
+        }
+    }
+}
+
+/// Edits the selected note, undoes and saves with the keyboard
+fn edit_with_keyboard(
+    keyboard_input: Res<Input<KeyCode>>,
+    time: Res<ControlledTime>,
+    playback: Res<Playback>,
+    mut chart: ResMut<EditorChart>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let control =
+        keyboard_input.pressed(KeyCode::LControl) || keyboard_input.pressed(KeyCode::RControl);
+    let shift = keyboard_input.pressed(KeyCode::LShift) || keyboard_input.pressed(KeyCode::RShift);
+
+    if control {
+        if keyboard_input.just_pressed(KeyCode::Z) {
+            if shift {
+                chart.redo();
+            } else {
+                chart.undo();
+            }
+        }
+        if keyboard_input.just_pressed(KeyCode::Y) {
+            chart.redo();
+        }
+        if keyboard_input.just_pressed(KeyCode::S) {
+            chart.save();
+        }
+        return;
+    }
+
+    let speeds = [
+        (KeyCode::Key1, Speed::Slow),
+        (KeyCode::Key2, Speed::Medium),
+        (KeyCode::Key3, Speed::Fast),
+    ];
+    for (key, speed) in speeds.iter() {
+        if keyboard_input.just_pressed(*key) {
+            chart.edit_selected(|arrow| arrow.speed = *speed);
+        }
+    }
+
+    let directions = [
+        (KeyCode::Up, Up),
+        (KeyCode::Down, Down),
+        (KeyCode::Left, Left),
+        (KeyCode::Right, Right),
+    ];
+    for (key, direction) in directions.iter() {
+        if keyboard_input.just_pressed(*key) {
+            chart.edit_selected(|arrow| arrow.direction = *direction);
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Delete) {
+        if let Some(index) = chart.selected {
+            chart.checkpoint();
+            chart.arrows_mut().remove(index);
+            chart.selected = None;
+        }
+    }
+
+    // Start the menu preview at the playhead
+    if keyboard_input.just_pressed(KeyCode::P) {
+        let song_time = playback.song_time(&time).max(0.);
+        if let Some(config) = &mut chart.config {
+            config.preview_start = Some((song_time * 100.).round() / 100.);
+            chart.dirty = true;
+        }
+    }
+
+    // Save and go back to the menu
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        chart.save();
+        state
+            .set(AppState::Menu)
+            .expect("Couldn't switch state to Menu");
+    }
+}
+
+/// Saves the chart and watches it played with autoplay, coming back to the editor once it's over
+fn autoplay_chart(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut chart: ResMut<EditorChart>,
+    mut mode: ResMut<PlayMode>,
+    mut return_state: ResMut<ReturnState>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F5) || chart.config.is_none() {
+        return;
+    }
+
+    chart.save();
+    let config = load_config(&format!("{}.toml", chart.stem));
+    commands.insert_resource(config);
+    *mode = PlayMode::Autoplay;
+    return_state.0 = Some(AppState::EditMap);
+    state
+        .set(AppState::Game)
+        .expect("Couldn't switch state to Game");
+}
+
+/// Redraws the timeline and the notes
+fn draw_timeline(
+    mut commands: Commands,
+    chart: Res<EditorChart>,
+    view: Res<TimelineView>,
+    materials: Res<TimelineMaterials>,
+    arrow_materials: Res<ArrowMaterialResource>,
+    query: Query<Entity, With<TimelineSprite>>,
+) {
+    if !chart.is_changed() && !view.is_changed() {
+        return;
+    }
+
+    clear_timeline(&mut commands, &query);
+    spawn_timeline_grid(&mut commands, &view, &materials, chart.beat_grid());
+
+    for (index, arrow) in chart.arrows().iter().enumerate() {
+        // Tails can be on screen when their note isn't
+        spawn_timeline_hold(&mut commands, &view, arrow, arrow_materials.hold_material());
+        if !view.is_visible(arrow.click_time) {
+            continue;
+        }
+
+        let material = arrow_materials.speed_material(arrow.speed);
+        spawn_timeline_note(
+            &mut commands,
+            &view,
+            arrow,
+            material,
+            TIMELINE_NOTE_SIZE,
+            1.,
+        );
+
+        // Draw a border around the selected note
+        if chart.selected == Some(index) {
+            let border = arrow_materials.border_material();
+            spawn_timeline_note(
+                &mut commands,
+                &view,
+                arrow,
+                border,
+                TIMELINE_NOTE_SIZE * 1.3,
+                1.1,
+            );
+        }
+    }
+}
+
+fn load_editor_song(
+    asset_server: Res<AssetServer>,
+    chart: Res<EditorChart>,
+    mut playback: ResMut<Playback>,
+) {
+    if let Some(config) = &chart.config {
+        let filename = library::resolve(&chart.stem, &config.filename);
+        playback.load(&asset_server, &filename, 0., false);
+    }
+}
+
+/// Line showing the current time in the song
+struct Playhead;
+/// Line showing the start or the end of the looped section
+struct LoopMarker {
+    start: bool,
+}
+
+fn setup_playhead(mut commands: Commands, materials: Res<TimelineMaterials>) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            material: materials.playhead.clone(),
+            sprite: Sprite::new(Vec2::new(3., 420.)),
+            transform: Transform::from_translation(Vec3::new(TIMELINE_START, 0., 2.)),
+            ..Default::default()
+        })
+        .insert(Playhead);
+
+    for start in [true, false].iter() {
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: materials.loop_marker.clone(),
+                sprite: Sprite::new(Vec2::new(3., 420.)),
+                transform: Transform::from_translation(Vec3::new(TIMELINE_START, 0., 1.9)),
+                ..Default::default()
+            })
+            .insert(LoopMarker { start: *start });
+    }
+}
+
+/// Moves the playhead and loop markers, scrolling the timeline to follow the song
+fn update_playhead(
+    time: Res<ControlledTime>,
+    playback: Res<Playback>,
+    mut view: ResMut<TimelineView>,
+    mut playhead: Query<&mut Transform, With<Playhead>>,
+    mut loop_markers: Query<(&mut Transform, &mut Visible, &LoopMarker), Without<Playhead>>,
+) {
+    let song_time = playback.song_time(&time);
+    if !time.is_paused() && (song_time < view.start || song_time > view.end()) {
+        view.start = song_time.max(0.);
+    }
+
+    for mut transform in playhead.iter_mut() {
+        transform.translation.x = view.x(song_time);
+    }
+
+    let region = playback.loop_region();
+    for (mut transform, mut visible, marker) in loop_markers.iter_mut() {
+        visible.is_visible = region.is_some();
+        if let Some((start, end)) = region {
+            transform.translation.x = view.x(if marker.start { start } else { end });
+        }
+    }
+}
+
+struct EditorText;
+fn setup_editor_ui(mut commands: Commands, materials: Res<TimelineMaterials>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    top: Val::Px(10.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font_size: 20.0,
+                    font: materials.font.clone(),
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(EditorText);
+}
+
+fn update_editor_text(
+    chart: Res<EditorChart>,
+    view: Res<TimelineView>,
+    mut query: Query<&mut Text, With<EditorText>>,
+) {
+    if !chart.is_changed() && !view.is_changed() {
+        return;
+    }
+
+    let name = chart
+        .config
+        .as_ref()
+        .map(|config| config.name.as_str())
+        .unwrap_or("");
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "{}{}. Notes: {}. View: {:.2}s. Zoom: {:.0}px/s\n\
+            Click: add/select/drag. Shift+click: seek. Right click/Del: delete. 1-3: speed.\n\
+            Arrows: direction. Tab: spectrogram. P: preview start. \
+            Ctrl+Z/Ctrl+Y: undo/redo. Ctrl+S: save. F5: autoplay. Enter: save and exit",
+            name,
+            if chart.dirty { " (unsaved)" } else { "" },
+            chart.arrows().len(),
+            view.start,
+            view.zoom,
+        );
+    }
+}
+
+fn despawn_editor(
+    mut commands: Commands,
+    sprites: Query<Entity, With<TimelineSprite>>,
+    texts: Query<Entity, With<EditorText>>,
+    playheads: Query<Entity, With<Playhead>>,
+    loop_markers: Query<Entity, With<LoopMarker>>,
+) {
+    for entity in sprites
+        .iter()
+        .chain(texts.iter())
+        .chain(playheads.iter())
+        .chain(loop_markers.iter())
+    {
+        commands.entity(entity).despawn();
+    }
+}
+
+pub struct EditorPlugin;
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<EditorChart>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::EditMap)
+                    .with_system(reset_timeline.system())
+                    .with_system(reset_editor_input.system())
+                    .with_system(load_editor_song.system())
+                    .with_system(setup_playhead.system())
+                    .with_system(setup_editor_ui.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::EditMap)
+                    .with_system(scroll_timeline.system())
+                    .with_system(edit_with_mouse.system())
+                    .with_system(edit_with_keyboard.system())
+                    .with_system(autoplay_chart.system())
+                    .with_system(draw_timeline.system())
+                    .with_system(update_playhead.system())
+                    .with_system(update_editor_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::EditMap).with_system(despawn_editor.system()),
+            );
+    }
+}
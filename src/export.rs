@@ -0,0 +1,87 @@
+use crate::cli::{exit_with_error, exit_with_usage};
+use crate::generator::{decode_song_file, estimate_beat_grid};
+use crate::library;
+use crate::stats::ChartStats;
+use crate::stepmania;
+use crate::types::{file_stem, parse_config, SongConfig};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const USAGE: &str = "Usage: bevy_rhythm export <chart...> [--out <folder>]
+
+Writes charts of the same song in `assets/songs` as a StepMania `.sm` file, next to a copy of the song's
+audio, so they can be played in other games. Notes are snapped to the chart's BPM, or to one estimated
+from the audio if it doesn't have one. Writes to `exports/<song>` unless a folder is given.";
+
+/// Exports charts from the command line
+pub fn run_cli(args: &[String]) {
+    let mut charts = vec![];
+    let mut out = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => match args.next() {
+                Some(folder) => out = Some(PathBuf::from(folder)),
+                None => exit_with_usage("Missing value for --out", USAGE),
+            },
+            flag if flag.starts_with("--") => {
+                exit_with_usage(&format!("Unknown option {}", flag), USAGE)
+            }
+            chart => charts.push(chart.trim_end_matches(".toml").to_string()),
+        }
+    }
+    let first = match charts.first() {
+        Some(first) => first.clone(),
+        None => exit_with_usage("Missing chart", USAGE),
+    };
+
+    let mut configs = vec![];
+    for chart in charts.iter() {
+        let mut config = parse_config(&format!("{}.toml", chart)).unwrap_or_else(|error| {
+            exit_with_error(&format!("Couldn't read {}: {}", chart, error))
+        });
+        // StepMania charts need a level, so the estimated one is used if it doesn't have one
+        if config.level.is_none() {
+            let stats = ChartStats::new(&SongConfig::new(chart, config.clone()));
+            config.level = Some(stats.rating.round().max(1.) as u32);
+        }
+        configs.push(config);
+    }
+
+    let audio = library::resolve(&first, &configs[0].filename);
+    for (chart, config) in charts.iter().zip(configs.iter()) {
+        if library::resolve(chart, &config.filename) != audio {
+            exit_with_error(&format!("{} is a chart of a different song", chart));
+        }
+    }
+    let grid = match configs.iter().find_map(|config| config.beat_grid()) {
+        Some(grid) => grid,
+        None => match decode_song_file(&audio) {
+            Some(song) => estimate_beat_grid(&song),
+            None => exit_with_error(&format!("Couldn't decode assets/songs/{}", audio)),
+        },
+    };
+
+    // The audio is copied next to the simfile
+    let audio_name = audio.rsplit('/').next().unwrap_or(&audio).to_string();
+    for config in configs.iter_mut() {
+        config.filename = audio_name.clone();
+    }
+    let bytes = library::read_file(&audio)
+        .unwrap_or_else(|_| exit_with_error(&format!("Couldn't read assets/songs/{}", audio)));
+
+    let stem = file_stem(&configs[0].name);
+    let folder = out.unwrap_or_else(|| Path::new("exports").join(&stem));
+    fs::create_dir_all(&folder).expect("Couldn't create export folder");
+    fs::write(folder.join(&audio_name), bytes).expect("Couldn't write audio file");
+    let path = folder.join(format!("{}.sm", stem));
+    fs::write(&path, stepmania::export(&configs, grid)).expect("Couldn't write simfile");
+    println!(
+        "Wrote {}: {} charts at {} BPM",
+        path.display(),
+        configs.len(),
+        grid.bpm
+    );
+}
@@ -0,0 +1,488 @@
+use crate::cli::{exit_with_error, exit_with_usage};
+use crate::consts::*;
+use crate::library;
+use crate::playback::DecodedSong;
+use crate::types::{
+    file_stem, fnv_hash, unused_chart_stem, write_config, ArrowTimeToml, BeatGrid,
+    Directions::{self, *},
+    SongConfigToml, Speed, Subdivision,
+};
+use bevy::audio::AudioSource;
+
+/// Onset detection frames per second
+const FRAMES_PER_SECOND: f64 = 100.;
+/// Number of frames on each side used for the adaptive onset threshold
+const THRESHOLD_FRAMES: usize = 15;
+/// Range of tempos the BPM estimation looks at
+const BPM_RANGE: (f64, f64) = (80., 170.);
+
+/// How many notes a generated chart has
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Expert,
+}
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Expert,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Expert => "Expert",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|difficulty| difficulty.name().eq_ignore_ascii_case(name))
+            .copied()
+    }
+
+    /// Returns the next difficulty, going back to Easy after Expert
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|d| d == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// Default generator settings for this difficulty
+    pub fn settings(&self) -> DifficultySettings {
+        match self {
+            Difficulty::Easy => DifficultySettings {
+                density: 1.,
+                min_gap: 0.45,
+                subdivision: Subdivision::Quarter,
+                speed: Speed::Slow,
+            },
+            Difficulty::Normal => DifficultySettings {
+                density: 2.,
+                min_gap: 0.25,
+                subdivision: Subdivision::Eighth,
+                speed: Speed::Slow,
+            },
+            Difficulty::Hard => DifficultySettings {
+                density: 3.5,
+                min_gap: 0.15,
+                subdivision: Subdivision::Eighth,
+                speed: Speed::Medium,
+            },
+            Difficulty::Expert => DifficultySettings {
+                density: 5.,
+                min_gap: 0.1,
+                subdivision: Subdivision::Sixteenth,
+                speed: Speed::Fast,
+            },
+        }
+    }
+}
+
+/// Controls how many notes get generated and where
+#[derive(Copy, Clone, Debug)]
+pub struct DifficultySettings {
+    /// Highest average number of notes per second
+    pub density: f64,
+    /// Shortest time between two notes, in seconds
+    pub min_gap: f64,
+    /// Notes get snapped to this subdivision of the detected beat
+    pub subdivision: Subdivision,
+    pub speed: Speed,
+}
+
+/// Moment where a new sound starts in the song
+#[derive(Copy, Clone, Debug)]
+pub struct Onset {
+    pub time: f64,
+    /// How far above the threshold the onset is
+    pub strength: f32,
+}
+
+/// How much louder each frame of the song is than the one before
+struct Envelope {
+    values: Vec<f32>,
+    /// Actual frames per second, which is close to `FRAMES_PER_SECOND`
+    frame_rate: f64,
+}
+
+fn onset_envelope(song: &DecodedSong) -> Envelope {
+    let channels = song.channels as usize;
+    let hop = ((song.sample_rate as f64 / FRAMES_PER_SECOND) as usize).max(1);
+
+    // Energy of the mix, and of its first difference to catch hi-hats and other high sounds
+    let mut energies = vec![];
+    let mut previous_sample = 0.;
+    for frame in song.samples.chunks(hop * channels) {
+        let (mut energy, mut high_energy) = (0f32, 0f32);
+        for samples in frame.chunks(channels) {
+            let sample = samples.iter().map(|s| *s as f32).sum::<f32>() / channels as f32 / 32768.;
+            energy += sample * sample;
+            high_energy += (sample - previous_sample) * (sample - previous_sample);
+            previous_sample = sample;
+        }
+        energies.push((energy, high_energy));
+    }
+
+    let mut envelope = vec![0.; energies.len()];
+    for i in 1..energies.len() {
+        let rise = |now: f32, before: f32| ((now + 1e-6).ln() - (before + 1e-6).ln()).max(0.);
+        envelope[i] =
+            rise(energies[i].0, energies[i - 1].0) + rise(energies[i].1, energies[i - 1].1);
+    }
+    Envelope {
+        values: envelope,
+        frame_rate: song.sample_rate as f64 / hop as f64,
+    }
+}
+
+/// Finds the peaks of the onset envelope that stand out from their surroundings
+pub fn detect_onsets(song: &DecodedSong) -> Vec<Onset> {
+    let Envelope {
+        values: envelope,
+        frame_rate,
+    } = onset_envelope(song);
+
+    let mut onsets = vec![];
+    for i in 0..envelope.len() {
+        let start = i.saturating_sub(THRESHOLD_FRAMES);
+        let end = (i + THRESHOLD_FRAMES + 1).min(envelope.len());
+        let around = &envelope[start..end];
+        let mean = around.iter().sum::<f32>() / around.len() as f32;
+        let threshold = mean * 1.5 + 0.05;
+
+        // Only keep the highest frame of each peak
+        let neighbours = &envelope[i.saturating_sub(3)..(i + 4).min(envelope.len())];
+        let is_peak = neighbours.iter().all(|value| *value <= envelope[i]);
+        if envelope[i] > threshold && is_peak {
+            onsets.push(Onset {
+                time: i as f64 / frame_rate,
+                strength: envelope[i] - threshold,
+            });
+        }
+    }
+    onsets
+}
+
+/// Returns how well beats at `bpm` starting at `offset` line up with the onset envelope
+fn beat_score(envelope: &Envelope, bpm: f64, offset: f64) -> f32 {
+    let step = 60. / bpm * envelope.frame_rate;
+    let mut position = offset * envelope.frame_rate;
+    let (mut total, mut count) = (0., 0);
+    while (position as usize) < envelope.values.len() {
+        total += envelope.values[position as usize];
+        count += 1;
+        position += step;
+    }
+    if count == 0 {
+        0.
+    } else {
+        total / count as f32
+    }
+}
+
+/// Returns the best offset for a BPM, and how well it scores
+fn best_offset(envelope: &Envelope, bpm: f64) -> (f64, f32) {
+    let beat_frames = (60. / bpm * envelope.frame_rate) as usize;
+    (0..beat_frames.max(1))
+        .map(|frame| {
+            let offset = frame as f64 / envelope.frame_rate;
+            (offset, beat_score(envelope, bpm, offset))
+        })
+        .fold((0., f32::MIN), |best, current| {
+            if current.1 > best.1 {
+                current
+            } else {
+                best
+            }
+        })
+}
+
+/// Guesses the tempo of the song and the time of its first beat
+pub fn estimate_beat_grid(song: &DecodedSong) -> BeatGrid {
+    let envelope = onset_envelope(song);
+
+    // Look at every half BPM first, then refine around the best one
+    let search = |bpms: Vec<f64>| {
+        bpms.into_iter()
+            .map(|bpm| {
+                let (offset, score) = best_offset(&envelope, bpm);
+                (bpm, offset, score)
+            })
+            .fold((BPM_RANGE.0, 0., f32::MIN), |best, current| {
+                if current.2 > best.2 {
+                    current
+                } else {
+                    best
+                }
+            })
+    };
+    let (low, high) = BPM_RANGE;
+    let (coarse, _, _) = search(
+        (0..=((high - low) * 2.) as usize)
+            .map(|i| low + i as f64 / 2.)
+            .collect(),
+    );
+    let (bpm, offset, score) = search((-10..=10).map(|i| coarse + i as f64 * 0.05).collect());
+
+    // Most songs have a whole number BPM, so prefer it when it fits almost as well
+    let (whole_offset, whole_score) = best_offset(&envelope, bpm.round());
+    if whole_score >= score * 0.95 {
+        return BeatGrid {
+            bpm: bpm.round(),
+            offset: whole_offset,
+        };
+    }
+
+    BeatGrid {
+        bpm: (bpm * 100.).round() / 100.,
+        offset,
+    }
+}
+
+/// Small deterministic random number generator, so the same seed always gives the same chart
+struct Rng(u64);
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Xorshift gets stuck on 0, so the one seed that would start there starts elsewhere
+        match seed ^ 0x9E37_79B9_7F4A_7C15 {
+            0 => Self(0x9E37_79B9_7F4A_7C15),
+            state => Self(state),
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+/// Returns a seed made from a name, so charts for the same song stay the same
+pub fn seed_from_name(name: &str) -> u64 {
+    fnv_hash(name.as_bytes())
+}
+
+/// Picks notes from the strongest onsets and spreads them across the lanes
+pub fn generate_arrows(
+    onsets: &[Onset],
+    grid: BeatGrid,
+    settings: DifficultySettings,
+    duration: f64,
+    seed: u64,
+) -> Vec<ArrowTimeToml> {
+    let mut strongest = onsets.to_vec();
+    strongest.sort_by(|a, b| b.strength.partial_cmp(&a.strength).unwrap());
+
+    // Take the strongest onsets that aren't too close to one already taken
+    let max_notes = (settings.density * duration).ceil() as usize;
+    let mut times: Vec<f64> = vec![];
+    for onset in strongest {
+        if times.len() >= max_notes {
+            break;
+        }
+        let time = grid.snap(onset.time, settings.subdivision);
+        if time >= 0.
+            && times
+                .iter()
+                .all(|other| (other - time).abs() >= settings.min_gap)
+        {
+            times.push(time);
+        }
+    }
+    times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let directions = [Up, Down, Left, Right];
+    let mut rng = Rng::new(seed);
+    let mut previous: Vec<Directions> = vec![];
+    times
+        .into_iter()
+        .map(|click_time| {
+            // Don't put three notes in a row on the same lane
+            let direction = loop {
+                let direction = directions[(rng.next() % directions.len() as u64) as usize];
+                let repeated = previous.len() >= 2
+                    && previous[previous.len() - 2..]
+                        .iter()
+                        .all(|other| *other == direction);
+                if !repeated {
+                    break direction;
+                }
+            };
+            previous.push(direction);
+
+            ArrowTimeToml {
+                click_time,
+                speed: settings.speed,
+                direction,
+                hold_end: None,
+            }
+        })
+        .collect()
+}
+
+/// Makes a draft chart for a song, ready to be polished in the editor
+pub fn generate_chart(
+    song: &DecodedSong,
+    name: &str,
+    filename: &str,
+    grid: Option<BeatGrid>,
+    difficulty: Difficulty,
+    settings: DifficultySettings,
+    seed: u64,
+) -> SongConfigToml {
+    let grid = grid.unwrap_or_else(|| estimate_beat_grid(song));
+    let duration = song.duration();
+    let arrows = generate_arrows(&detect_onsets(song), grid, settings, duration, seed);
+
+    SongConfigToml {
+        format_version: CHART_FORMAT_VERSION,
+        name: name.to_string(),
+        artist: None,
+        difficulty: Some(difficulty.name().to_string()),
+        level: None,
+        filename: filename.to_string(),
+        bpm: Some(grid.bpm),
+        offset: Some(grid.offset),
+        preview_start: None,
+        arrows,
+    }
+}
+
+/// Decodes an audio file in `assets/songs`
+pub fn decode_song_file(filename: &str) -> Option<DecodedSong> {
+    let bytes = library::read_file(filename).ok()?;
+    DecodedSong::load_cached(
+        filename,
+        &AudioSource {
+            bytes: bytes.into(),
+        },
+    )
+}
+
+/// Returns the file stem of a chart for one difficulty
+pub fn difficulty_stem(stem: &str, difficulty: Difficulty) -> String {
+    format!("{}_{}", stem, difficulty.name().to_lowercase())
+}
+
+const USAGE: &str = "\
+Usage: bevy_rhythm generate <audio file in assets/songs> [options]
+
+Writes a draft chart for each difficulty next to the audio. Chart files that already exist aren't
+overwritten, the new ones are numbered instead.
+
+Options:
+  --name <name>          Song name, defaults to the audio file name
+  --difficulty <name>    easy, normal, hard, expert or all (default: all)
+  --seed <number>        Seed used to pick lanes, defaults to one made from the name
+  --density <number>     Highest average notes per second, overriding the difficulty's
+  --bpm <number>         Tempo to snap notes to, instead of detecting it
+  --offset <seconds>     Time of the first beat, used with --bpm";
+
+/// Generates charts from the command line, without opening a window
+pub fn run_cli(args: &[String]) {
+    let mut args = args.iter();
+    let filename = match args.next() {
+        Some(filename) if !filename.starts_with("--") => filename.clone(),
+        _ => exit_with_usage("Missing audio file", USAGE),
+    };
+
+    let mut name = filename
+        .rsplit_once('.')
+        .map(|(stem, _)| stem.to_string())
+        .unwrap_or_else(|| filename.clone());
+    let mut difficulties = Difficulty::ALL.to_vec();
+    let mut seed = None;
+    let mut density = None;
+    let mut bpm = None;
+    let mut offset = None;
+
+    while let Some(flag) = args.next() {
+        let value = match args.next() {
+            Some(value) => value,
+            None => exit_with_usage(&format!("Missing value for {}", flag), USAGE),
+        };
+        let number = || {
+            value
+                .parse::<f64>()
+                .unwrap_or_else(|_| exit_with_usage(&format!("Invalid number for {}", flag), USAGE))
+        };
+        match flag.as_str() {
+            "--name" => name = value.clone(),
+            "--difficulty" if value == "all" => difficulties = Difficulty::ALL.to_vec(),
+            "--difficulty" => match Difficulty::from_name(value) {
+                Some(difficulty) => difficulties = vec![difficulty],
+                None => exit_with_usage(&format!("Unknown difficulty {}", value), USAGE),
+            },
+            "--seed" => {
+                seed = Some(
+                    value
+                        .parse()
+                        .unwrap_or_else(|_| exit_with_usage("Invalid number for --seed", USAGE)),
+                )
+            }
+            "--density" => density = Some(number()),
+            "--bpm" => {
+                let value = number();
+                if !(value > 0. && value.is_finite()) {
+                    exit_with_usage("--bpm has to be a positive number", USAGE);
+                }
+                bpm = Some(value);
+            }
+            "--offset" => offset = Some(number()),
+            _ => exit_with_usage(&format!("Unknown option {}", flag), USAGE),
+        }
+    }
+    if offset.is_some() && bpm.is_none() {
+        exit_with_usage("--offset can only be used with --bpm", USAGE);
+    }
+
+    let song = decode_song_file(&filename)
+        .unwrap_or_else(|| exit_with_error(&format!("Couldn't decode assets/songs/{}", filename)));
+    let grid = Some(match bpm {
+        Some(bpm) => BeatGrid {
+            bpm,
+            offset: offset.unwrap_or(0.),
+        },
+        None => estimate_beat_grid(&song),
+    });
+
+    let stem = file_stem(&name);
+    let seed = seed.unwrap_or_else(|| seed_from_name(&stem));
+
+    for difficulty in difficulties {
+        let mut settings = difficulty.settings();
+        if let Some(density) = density {
+            settings.density = density;
+        }
+
+        let config = generate_chart(
+            &song,
+            name.trim(),
+            &filename,
+            grid,
+            difficulty,
+            settings,
+            seed,
+        );
+        // Charts that were already generated may have been edited, so they're kept
+        let path = format!(
+            "{}.toml",
+            unused_chart_stem(&difficulty_stem(&stem, difficulty))
+        );
+        write_config(&path, &config);
+        println!(
+            "Wrote assets/songs/{}: {} notes at {} BPM",
+            path,
+            config.arrows.len(),
+            config.bpm.unwrap_or_default()
+        );
+    }
+}
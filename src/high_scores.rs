@@ -0,0 +1,272 @@
+use crate::arrows::PlayMode;
+use crate::consts::*;
+use crate::replay::CurrentReplay;
+use crate::score::{Judgement, ScoreResource};
+use crate::settings::{data_folder, now, Settings};
+use crate::types::SongConfig;
+use bevy::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use std::{fs, io, path::PathBuf};
+
+/// One play of a chart, as saved in the high scores file
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PlayRecord {
+    /// Path of the chart in `assets/songs`, without its extension
+    pub chart: String,
+    /// Hash of the chart's notes when it was played
+    pub chart_hash: String,
+    pub score: usize,
+    /// Points scored out of the most that could be scored, from 0 to 1
+    pub accuracy: f64,
+    pub perfect: usize,
+    pub great: usize,
+    pub good: usize,
+    pub miss: usize,
+    pub max_combo: usize,
+    /// Seconds since the Unix epoch
+    pub played_at: u64,
+}
+impl PlayRecord {
+    pub fn new(config: &SongConfig, score: &ScoreResource) -> Self {
+        Self {
+            chart: config.chart.clone(),
+            chart_hash: config.chart_hash.clone(),
+            score: score.score(),
+            accuracy: score.accuracy(),
+            perfect: score.count(Judgement::Perfect),
+            great: score.count(Judgement::Great),
+            good: score.count(Judgement::Good),
+            miss: score.count(Judgement::Miss),
+            max_combo: score.max_combo(),
+            played_at: now(),
+        }
+    }
+
+    /// Returns the day it was played, as year-month-day in UTC
+    pub fn date(&self) -> String {
+        // Converts days since the epoch to a date in the proleptic Gregorian calendar
+        let days = (self.played_at / 86400) as i64 + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        format!("{}-{:02}-{:02}", year, month, day)
+    }
+}
+
+/// Every play saved on this computer
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct HighScores {
+    plays: Vec<PlayRecord>,
+    /// Set when a file that couldn't be read couldn't be moved out of the way either,
+    /// so it isn't overwritten by the plays of this session
+    #[serde(skip)]
+    read_only: bool,
+}
+impl HighScores {
+    /// Reads the high scores file, or starts with no plays if there isn't one.
+    /// A file that can't be read is kept as a backup instead of being overwritten
+    pub fn load() -> Self {
+        let path = high_scores_path();
+        let loaded = match fs::read_to_string(&path) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Self::default(),
+            Err(error) => Err(error.to_string()),
+            Ok(text) => toml::from_str(&text).map_err(|error| error.to_string()),
+        };
+        let error = match loaded {
+            Ok(high_scores) => return high_scores,
+            Err(error) => error,
+        };
+
+        let backup = path.with_extension(format!("{}.toml", now()));
+        match fs::rename(&path, &backup) {
+            Ok(()) => {
+                warn!(
+                    "Couldn't read {}, starting with no plays. It was moved to {}: {}",
+                    path.display(),
+                    backup.display(),
+                    error
+                );
+                Self::default()
+            }
+            Err(_) => {
+                warn!(
+                    "Couldn't read {}, so plays won't be saved: {}",
+                    path.display(),
+                    error
+                );
+                Self {
+                    read_only: true,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        if self.read_only {
+            return;
+        }
+        let path = high_scores_path();
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder).expect("Couldn't create high scores folder");
+        }
+        let text = toml::to_string(self).expect("Couldn't convert to toml text");
+        fs::write(path, text).expect("Couldn't write high scores file");
+    }
+
+    /// Returns the plays of this version of a chart
+    fn plays_of(&self, chart: &str, chart_hash: &str) -> Vec<&PlayRecord> {
+        self.plays
+            .iter()
+            .filter(|play| play.chart == chart && play.chart_hash == chart_hash)
+            .collect()
+    }
+
+    /// Returns the best play of this version of a chart
+    pub fn best(&self, chart: &str, chart_hash: &str) -> Option<&PlayRecord> {
+        self.plays_of(chart, chart_hash)
+            .into_iter()
+            .max_by_key(|play| play.score)
+    }
+
+    /// Saves a play, returning the plays of the chart before it
+    pub fn add(&mut self, play: PlayRecord) -> Vec<PlayRecord> {
+        let previous = self
+            .plays_of(&play.chart, &play.chart_hash)
+            .into_iter()
+            .cloned()
+            .collect();
+        self.plays.push(play);
+        self.save();
+        previous
+    }
+}
+
+fn high_scores_path() -> PathBuf {
+    data_folder().join("scores.toml")
+}
+
+/// The play that just finished, shown in the results screen
+#[derive(Default)]
+pub struct LastPlay {
+    pub title: String,
+    pub play: PlayRecord,
+    /// How the song was played, only plays of the player can be records
+    pub mode: PlayMode,
+    /// Plays of the chart before this one
+    pub previous: Vec<PlayRecord>,
+    /// When watching a replay, whether it scored the same as when it was recorded
+    pub replay_matches: Option<bool>,
+}
+impl LastPlay {
+    /// Checks if a value of the play beats the best of that value in the previous plays,
+    /// so it can be marked as a new record
+    pub fn is_record<T: PartialOrd>(&self, value: impl Fn(&PlayRecord) -> T) -> bool {
+        if self.mode != PlayMode::Play {
+            return false;
+        }
+        let new = value(&self.play);
+        self.previous.iter().all(|previous| new > value(previous))
+    }
+}
+
+/// Saves the play and its replay once a song finishes, unless it was a replay being watched or autoplay
+fn save_play(
+    song_config: Res<SongConfig>,
+    score: Res<ScoreResource>,
+    settings: Res<Settings>,
+    mode: Res<PlayMode>,
+    mut current: ResMut<CurrentReplay>,
+    mut high_scores: ResMut<HighScores>,
+    mut last_play: ResMut<LastPlay>,
+) {
+    let replay = &mut current.replay;
+    let play = PlayRecord::new(&song_config, &score);
+
+    let (previous, replay_matches) = match *mode {
+        // Neither counts as a record, so the previous plays aren't needed
+        PlayMode::Watch => (vec![], Some(replay.score == play.score)),
+        // Autoplay isn't a play of the player, so nothing is saved
+        PlayMode::Autoplay => (vec![], None),
+        PlayMode::Play => {
+            replay.chart = play.chart.clone();
+            replay.chart_hash = play.chart_hash.clone();
+            replay.settings = settings.clone();
+            replay.score = play.score;
+            replay.played_at = play.played_at;
+            replay.save();
+            (high_scores.add(play.clone()), None)
+        }
+    };
+
+    *last_play = LastPlay {
+        title: song_config.name.clone(),
+        play,
+        mode: *mode,
+        previous,
+        replay_matches,
+    };
+}
+
+pub struct HighScoresPlugin;
+impl Plugin for HighScoresPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(HighScores::load())
+            .init_resource::<LastPlay>()
+            .add_system_set(SystemSet::on_enter(AppState::Results).with_system(save_play.system()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(score: usize, accuracy: f64, max_combo: usize) -> PlayRecord {
+        PlayRecord {
+            score,
+            accuracy,
+            max_combo,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn records_are_checked_against_the_best_of_each_value() {
+        let last_play = LastPlay {
+            play: play(900, 0.9, 50),
+            // The best score has a lower max combo than another play
+            previous: vec![play(800, 0.8, 20), play(500, 0.95, 60)],
+            ..Default::default()
+        };
+        assert!(last_play.is_record(|play| play.score));
+        assert!(!last_play.is_record(|play| play.accuracy));
+        assert!(!last_play.is_record(|play| play.max_combo));
+
+        let first_play = LastPlay {
+            play: play(100, 0.1, 1),
+            ..Default::default()
+        };
+        assert!(first_play.is_record(|play| play.max_combo));
+
+        // Only plays of the player are records
+        for mode in [PlayMode::Watch, PlayMode::Autoplay].iter() {
+            let not_played = LastPlay {
+                play: play(100, 0.1, 1),
+                mode: *mode,
+                ..Default::default()
+            };
+            assert!(!not_played.is_record(|play| play.score));
+        }
+    }
+}
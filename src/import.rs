@@ -0,0 +1,90 @@
+use crate::cli::{exit_with_error, exit_with_usage};
+use crate::library;
+use crate::osu;
+use crate::stepmania;
+use crate::types::{file_stem, write_config, SongConfigToml};
+use std::path::Path;
+
+// Charts of other games in `assets/songs` are converted when they're loaded, so they can be
+// played without converting them first. The chart names tell them apart from chart files.
+
+/// Imports a chart of another game, or returns `None` if it isn't named like one
+pub fn import_chart(chart: &str) -> Option<Result<SongConfigToml, String>> {
+    stepmania::import_chart(chart).or_else(|| osu::import_chart(chart))
+}
+
+/// Checks if a chart is named like a chart of another game, which has no chart file
+pub fn is_imported(chart: &str) -> bool {
+    let simfile_chart = chart.rsplit_once('#');
+    osu::is_beatmap(chart) || simfile_chart.is_some_and(|(file, _)| stepmania::is_simfile(file))
+}
+
+/// Returns the names of the charts that can be imported from a file, `files` being every file in `assets/songs`
+pub fn find_charts(file: &str, files: &[String]) -> Vec<String> {
+    if stepmania::is_simfile(file) {
+        // Songs often have both, and the `.ssc` is the newer one
+        let ssc = format!("{}.ssc", file.trim_end_matches(".sm"));
+        if file.ends_with(".sm") && files.contains(&ssc) {
+            return vec![];
+        }
+        return stepmania::find_charts(file);
+    }
+    if osu::is_beatmap(file) {
+        return osu::find_charts(file);
+    }
+    vec![]
+}
+
+const USAGE: &str = "Usage: bevy_rhythm import <file>
+
+Converts a StepMania `.sm` or `.ssc` file, or an osu!mania `.osu` beatmap, in `assets/songs` into
+chart files written next to it. Every 4 panel chart of a StepMania file is converted.
+Chart files that already exist aren't overwritten.";
+
+/// Converts charts of other games from the command line
+pub fn run_cli(args: &[String]) {
+    let file = match args {
+        [file] => file,
+        [] => exit_with_usage("Missing file", USAGE),
+        _ => exit_with_usage("Too many arguments", USAGE),
+    };
+    if library::is_archived(file) {
+        exit_with_usage("Charts can't be written into a zipped pack", USAGE);
+    }
+
+    let configs = if stepmania::is_simfile(file) {
+        stepmania::import_all(file)
+    } else if osu::is_beatmap(file) {
+        osu::import(file).map(|config| vec![config])
+    } else {
+        exit_with_usage(&format!("{} isn't a .sm, .ssc or .osu file", file), USAGE)
+    };
+    let configs = configs.unwrap_or_else(|error| {
+        exit_with_error(&format!("Couldn't import assets/songs/{}: {}", file, error))
+    });
+
+    let paths: Vec<String> = configs
+        .iter()
+        .map(|config| {
+            let difficulty = config.difficulty.as_deref().unwrap_or("chart");
+            let stem = format!("{}_{}", file_stem(&config.name), file_stem(difficulty));
+            format!("{}.toml", library::resolve(file, &stem))
+        })
+        .collect();
+    // Nothing is written if a chart would be overwritten, since it may have been edited
+    let existing: Vec<&String> = paths
+        .iter()
+        .filter(|path| Path::new(&format!("assets/songs/{}", path)).exists())
+        .collect();
+    if !existing.is_empty() {
+        for path in existing {
+            eprintln!("assets/songs/{} already exists", path);
+        }
+        exit_with_error("Move or delete the charts to import them again");
+    }
+
+    for (config, path) in configs.iter().zip(paths.iter()) {
+        write_config(path, config);
+        println!("Wrote assets/songs/{}: {} notes", path, config.arrows.len());
+    }
+}
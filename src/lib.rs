@@ -0,0 +1,106 @@
+//! Rhythm game gameplay for Bevy apps. Add `RhythmPlugins` to play songs,
+//! or `SimulationPlugins` to run the rules of the game without a window.
+//! Insert a `RhythmConfig` before adding them to change the layout, assets, states or keys.
+use bevy::{app::PluginGroupBuilder, prelude::*};
+
+mod arrows;
+use arrows::{ArrowSpritesPlugin, ArrowsPlugin};
+mod config;
+use config::ConfigPlugin;
+mod consts;
+mod types;
+mod ui;
+use ui::UIPlugin;
+mod audio;
+mod score;
+use audio::AudioPlugin;
+mod shaders;
+use shaders::ShadersPlugin;
+mod menu;
+use menu::MenuPlugin;
+mod time;
+use time::TimePlugin;
+mod map_maker;
+use map_maker::MapMakerPlugin;
+mod editor;
+use editor::EditorPlugin;
+mod timeline;
+use timeline::TimelinePlugin;
+mod playback;
+use playback::PlaybackPlugin;
+mod waveform;
+use waveform::WaveformPlugin;
+pub mod generator;
+mod metronome;
+use metronome::MetronomePlugin;
+mod sfx;
+use sfx::SfxPlugin;
+mod settings;
+use settings::SettingsPlugin;
+mod preview;
+use preview::PreviewPlugin;
+mod high_scores;
+use high_scores::HighScoresPlugin;
+mod library;
+mod song_select;
+use song_select::SongSelectPlugin;
+mod replay;
+use replay::ReplayPlugin;
+mod autoplay;
+use autoplay::AutoplayPlugin;
+pub mod chart_format;
+mod cli;
+pub mod export;
+pub mod import;
+mod osu;
+pub mod simulation;
+pub mod stats;
+mod stepmania;
+pub mod validator;
+
+pub use arrows::{CorrectArrowEvent, JudgementEvent, LaneInput, PlayMode};
+pub use config::{AssetPaths, GameStates, KeyBindings, Layout, RhythmConfig};
+pub use consts::AppState;
+pub use replay::{Replay, WatchReplay};
+pub use score::{Judgement, ScoreResource};
+pub use time::ControlledTime;
+pub use types::{load_config, ArrowTime, Directions, SongConfig, SongConfigToml, Speed};
+
+/// Every plugin of the game, which needs `DefaultPlugins` and a camera
+pub struct RhythmPlugins;
+impl PluginGroup for RhythmPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        SimulationPlugins.build(group);
+        group
+            .add(ArrowSpritesPlugin)
+            .add(UIPlugin)
+            .add(AudioPlugin)
+            .add(ShadersPlugin)
+            .add(MenuPlugin)
+            .add(TimePlugin)
+            .add(MapMakerPlugin)
+            .add(EditorPlugin)
+            .add(TimelinePlugin)
+            .add(PlaybackPlugin)
+            .add(WaveformPlugin)
+            .add(MetronomePlugin)
+            .add(SfxPlugin)
+            .add(SettingsPlugin)
+            .add(PreviewPlugin)
+            .add(SongSelectPlugin)
+            .add(HighScoresPlugin);
+    }
+}
+
+/// Plugins with the rules of the game: timing, spawning, judging and scoring.
+/// They run under `MinimalPlugins`, with `ControlledTime` stepped by hand
+pub struct SimulationPlugins;
+impl PluginGroup for SimulationPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group
+            .add(ConfigPlugin)
+            .add(ArrowsPlugin)
+            .add(ReplayPlugin)
+            .add(AutoplayPlugin);
+    }
+}
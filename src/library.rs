@@ -0,0 +1,151 @@
+use crate::consts::*;
+use crate::import;
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::Path,
+};
+use zip::ZipArchive;
+
+// Charts and audio files are named by their path inside `assets/songs`, using `/` between folders.
+// Paths that go through a `.zip` file point inside the archive, like `pack.zip/song/chart.toml`.
+
+/// Splits a path into the archive it's in and the path inside the archive
+fn split_archive(path: &str) -> Option<(&str, &str)> {
+    let end = path.find(".zip/")? + ".zip".len();
+    Some((&path[..end], &path[end + 1..]))
+}
+
+/// Checks if a path points inside a zipped pack, which can't be written to
+pub fn is_archived(path: &str) -> bool {
+    split_archive(path).is_some()
+}
+
+/// Reads a file from `assets/songs`, looking inside zipped packs
+pub fn read_file(path: &str) -> io::Result<Vec<u8>> {
+    let (archive, inner) = match split_archive(path) {
+        Some(split) => split,
+        None => return fs::read(format!("assets/songs/{}", path)),
+    };
+
+    let file = File::open(format!("assets/songs/{}", archive))?;
+    let mut archive = ZipArchive::new(file).map_err(zip_error)?;
+    let mut file = archive.by_name(inner).map_err(zip_error)?;
+    let mut bytes = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn zip_error(error: zip::result::ZipError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Returns the folder a chart is in, which is empty for charts at the top of `assets/songs`
+pub fn song_folder(chart: &str) -> &str {
+    chart
+        .rsplit_once('/')
+        .map(|(folder, _)| folder)
+        .unwrap_or("")
+}
+
+/// Returns the path of a file next to a chart, like the chart's audio
+pub fn resolve(chart: &str, filename: &str) -> String {
+    match song_folder(chart) {
+        "" => filename.to_string(),
+        folder => format!("{}/{}", folder, filename),
+    }
+}
+
+/// Returns the pack a chart belongs to, which is the top folder or archive of `pack/song/chart`
+pub fn pack(chart: &str) -> Option<String> {
+    let mut parts = chart.split('/');
+    let pack = parts.next()?;
+    if parts.count() < 2 {
+        return None;
+    }
+    Some(pack.trim_end_matches(".zip").to_string())
+}
+
+/// Reads the banner image of a chart's song, if it has its own folder
+pub fn read_banner(chart: &str) -> Option<Vec<u8>> {
+    if song_folder(chart).is_empty() {
+        return None;
+    }
+    read_file(&resolve(chart, BANNER_FILENAME)).ok()
+}
+
+/// Returns the paths without extension of every chart in `assets/songs`, its folders and its packs
+pub fn find_charts() -> Vec<String> {
+    let mut files = vec![];
+    for file in walk("") {
+        if file.ends_with(".zip") {
+            files.extend(
+                archive_files(&file)
+                    .iter()
+                    .map(|inner| format!("{}/{}", file, inner)),
+            );
+        } else {
+            files.push(file);
+        }
+    }
+
+    let mut charts = vec![];
+    for file in files.iter() {
+        if let Some(chart) = file.strip_suffix(".toml") {
+            charts.push(chart.to_string());
+        } else {
+            charts.extend(import::find_charts(file, &files));
+        }
+    }
+    // Imported charts that were saved from the editor are listed once
+    charts.sort();
+    charts.dedup();
+    charts
+}
+
+/// Returns the paths of the audio files in `assets/songs` and its folders, without the packs
+pub fn find_audio() -> Vec<String> {
+    let mut files: Vec<String> = walk("")
+        .into_iter()
+        .filter(|file| {
+            let extension = file.rsplit_once('.').map(|(_, ext)| ext).unwrap_or("");
+            AUDIO_EXTENSIONS.contains(&extension)
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Lists the files in a folder of `assets/songs` and its subfolders
+fn walk(folder: &str) -> Vec<String> {
+    let entries = match fs::read_dir(Path::new("assets/songs").join(folder)) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    let mut files = vec![];
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = match folder {
+            "" => name,
+            folder => format!("{}/{}", folder, name),
+        };
+        if entry.path().is_dir() {
+            files.extend(walk(&path));
+        } else {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Lists the files inside a zipped pack
+fn archive_files(archive: &str) -> Vec<String> {
+    let archive = File::open(format!("assets/songs/{}", archive))
+        .ok()
+        .and_then(|file| ZipArchive::new(file).ok());
+    match archive {
+        Some(archive) => archive.file_names().map(str::to_string).collect(),
+        None => vec![],
+    }
+}
@@ -1,26 +1,24 @@
 use bevy::{input::system::exit_on_esc_system, prelude::*};
-
-mod arrows;
-use arrows::ArrowsPlugin;
-mod consts;
-use consts::*;
-mod types;
-mod ui;
-use ui::UIPlugin;
-mod score;
-use score::ScoreResource;
-mod audio;
-use audio::AudioPlugin;
-mod shaders;
-use shaders::ShadersPlugin;
-mod menu;
-use menu::MenuPlugin;
-mod time;
-use time::TimePlugin;
-mod map_maker;
-use map_maker::MapMakerPlugin;
+use bevy_rhythm::{
+    chart_format, export, generator, import, simulation, stats, validator, RhythmPlugins,
+};
 
 fn main() {
+    // Subcommands work on charts without opening a window: `generate` makes them, `simulate` plays them,
+    // `validate` checks them, `stats` rates them, `import` converts charts of other games,
+    // `export` writes them for StepMania and `migrate` updates them to the current chart format
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("generate") => return generator::run_cli(&args[1..]),
+        Some("simulate") => return simulation::run_cli(&args[1..]),
+        Some("validate") => return validator::run_cli(&args[1..]),
+        Some("stats") => return stats::run_cli(&args[1..]),
+        Some("import") => return import::run_cli(&args[1..]),
+        Some("export") => return export::run_cli(&args[1..]),
+        Some("migrate") => return chart_format::run_cli(&args[1..]),
+        _ => {}
+    }
+
     App::build()
         // Set antialiasing to use 4 samples
         .insert_resource(Msaa { samples: 4 })
@@ -31,18 +29,10 @@ fn main() {
             height: 600.,
             ..Default::default()
         })
-        .add_state(AppState::Menu)
-        .init_resource::<ScoreResource>()
         .add_startup_system(setup.system())
         .add_system(exit_on_esc_system.system())
         .add_plugins(DefaultPlugins)
-        .add_plugin(ArrowsPlugin)
-        .add_plugin(UIPlugin)
-        .add_plugin(AudioPlugin)
-        .add_plugin(ShadersPlugin)
-        .add_plugin(MenuPlugin)
-        .add_plugin(TimePlugin)
-        .add_plugin(MapMakerPlugin)
+        .add_plugins(RhythmPlugins)
         .run();
 }
 
@@ -1,48 +1,317 @@
+use crate::arrows::ArrowMaterialResource;
+use crate::config::RhythmConfig;
 use crate::consts::*;
+use crate::generator::Difficulty;
+use crate::playback::Playback;
 use crate::time::ControlledTime;
+use crate::timeline::*;
 use crate::types::{
-    ArrowTimeToml,
+    file_stem, unused_chart_stem, write_config, ArrowTimeToml, BeatGrid,
     Directions::{self, *},
-    Speed,
+    SongConfigToml, Speed, Subdivision,
 };
 use bevy::{
     app::AppExit,
     input::{keyboard::KeyCode, Input},
     prelude::*,
 };
-use serde_derive::Serialize;
-use std::fs::File;
-use std::io::prelude::*;
 
-#[derive(Serialize, Debug, Default)]
+#[derive(Debug, Default)]
 struct Presses {
     arrows: Vec<ArrowTimeToml>,
 }
+impl Presses {
+    /// Returns the presses moved to the closest subdivision, without duplicates
+    fn snapped(&self, grid: BeatGrid, subdivision: Option<Subdivision>) -> Vec<ArrowTimeToml> {
+        let subdivision = match subdivision {
+            Some(subdivision) => subdivision,
+            None => return self.arrows.clone(),
+        };
+
+        let mut arrows: Vec<ArrowTimeToml> = vec![];
+        for arrow in &self.arrows {
+            let click_time = grid.snap(arrow.click_time, subdivision);
+            // Two presses can end up in the same place in the same lane
+            let duplicate = arrows.iter().any(|other| {
+                other.direction == arrow.direction && (other.click_time - click_time).abs() < 1e-6
+            });
+            if !duplicate {
+                arrows.push(ArrowTimeToml {
+                    click_time,
+                    ..arrow.clone()
+                });
+            }
+        }
+        arrows
+    }
+}
+
+/// Clears presses left over from a previous map
+fn reset_presses(mut presses: ResMut<Presses>) {
+    presses.arrows.clear();
+}
 
 fn save_key_presses(
     time: Res<ControlledTime>,
+    playback: Res<Playback>,
     keyboard_input: Res<Input<KeyCode>>,
+    config: Res<RhythmConfig>,
     mut presses: ResMut<Presses>,
 ) {
+    let click_time = playback.song_time(&time);
+    if click_time < 0. || time.is_paused() {
+        return;
+    }
+
     let directions = [Up, Down, Left, Right];
     for direction in directions.iter() {
-        if direction.key_just_pressed(&keyboard_input) {
+        if config.keys.just_pressed(*direction, &keyboard_input) {
             presses.arrows.push(ArrowTimeToml {
-                click_time: time.seconds_since_startup(),
+                click_time,
                 speed: Speed::Slow,
                 direction: *direction,
+                hold_end: None,
             });
         }
     }
 }
 
-fn save_to_file_on_exit(mut event_reader: EventReader<AppExit>, presses: Res<Presses>) {
+/// Song picked in the song picker, which the map maker records a chart for
+#[derive(Debug)]
+pub struct MapMakerSong {
+    /// Name of the song, which also decides the chart's file name
+    pub name: String,
+    /// Audio file inside `assets/songs`
+    pub filename: Option<String>,
+    pub bpm: f64,
+    /// Time of the first beat, in seconds
+    pub offset: f64,
+    /// Subdivision of a beat that presses get snapped to, if any
+    pub subdivision: Option<Subdivision>,
+    /// Difficulty of the charts made by the generator
+    pub difficulty: Difficulty,
+    /// What went wrong with the last button pressed in the song picker
+    pub error: Option<String>,
+}
+impl Default for MapMakerSong {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            filename: None,
+            bpm: 120.,
+            offset: 0.,
+            subdivision: None,
+            difficulty: Difficulty::Normal,
+            error: None,
+        }
+    }
+}
+impl MapMakerSong {
+    /// Returns the file stem the chart will be saved with
+    pub fn chart_stem(&self) -> String {
+        file_stem(&self.name)
+    }
+
+    /// Checks if there's enough information to start recording
+    pub fn is_ready(&self) -> bool {
+        self.filename.is_some() && !self.chart_stem().is_empty()
+    }
+
+    pub fn beat_grid(&self) -> BeatGrid {
+        BeatGrid {
+            bpm: self.bpm,
+            offset: self.offset,
+        }
+    }
+}
+
+/// Writes the recorded presses as a new chart in `assets/songs`, numbering it if the name is taken
+fn save_chart(presses: &Presses, song: &MapMakerSong) {
+    let filename = match &song.filename {
+        Some(filename) => filename.clone(),
+        None => return,
+    };
+
+    let config = SongConfigToml {
+        format_version: CHART_FORMAT_VERSION,
+        name: song.name.trim().to_string(),
+        artist: None,
+        difficulty: None,
+        level: None,
+        filename,
+        bpm: Some(song.bpm),
+        offset: Some(song.offset),
+        preview_start: None,
+        arrows: presses.snapped(song.beat_grid(), song.subdivision),
+    };
+    let stem = unused_chart_stem(&song.chart_stem());
+    write_config(&format!("{}.toml", stem), &config);
+    info!("Saved the recorded chart as assets/songs/{}.toml", stem);
+}
+
+fn save_to_file_on_exit(
+    mut event_reader: EventReader<AppExit>,
+    presses: Res<Presses>,
+    song: Res<MapMakerSong>,
+) {
     for _event in event_reader.iter() {
-        let text = toml::to_string(&*presses).expect("Couldn't convert to toml text");
+        save_chart(&presses, &song);
+    }
+}
 
-        let mut file = File::create("map.toml").expect("Couldn't open map.toml");
-        file.write_all(text.as_bytes())
-            .expect("Couldn't write to map.toml");
+/// Stops recording and shows the export preview when Enter is pressed
+fn finish_map(keyboard_input: Res<Input<KeyCode>>, mut state: ResMut<State<AppState>>) {
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        state
+            .set(AppState::ExportMap)
+            .expect("Couldn't switch state to ExportMap");
+    }
+}
+
+/// Changes the BPM, offset and subdivision used to snap the presses
+fn adjust_quantization(keyboard_input: Res<Input<KeyCode>>, mut song: ResMut<MapMakerSong>) {
+    let shift = keyboard_input.pressed(KeyCode::LShift) || keyboard_input.pressed(KeyCode::RShift);
+
+    let bpm_step = if shift { 0.1 } else { 1. };
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        song.bpm += bpm_step;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        song.bpm = (song.bpm - bpm_step).max(1.);
+    }
+
+    let offset_step = if shift { 0.001 } else { 0.01 };
+    if keyboard_input.just_pressed(KeyCode::Right) {
+        song.offset += offset_step;
+    }
+    if keyboard_input.just_pressed(KeyCode::Left) {
+        song.offset -= offset_step;
+    }
+
+    let subdivisions = [
+        (KeyCode::Key0, None),
+        (KeyCode::Key1, Some(Subdivision::Quarter)),
+        (KeyCode::Key2, Some(Subdivision::Eighth)),
+        (KeyCode::Key3, Some(Subdivision::Sixteenth)),
+        (KeyCode::Key4, Some(Subdivision::EighthTriplet)),
+        (KeyCode::Key5, Some(Subdivision::SixteenthTriplet)),
+    ];
+    for (key, subdivision) in subdivisions.iter() {
+        if keyboard_input.just_pressed(*key) {
+            song.subdivision = *subdivision;
+        }
+    }
+}
+
+/// Draws the raw presses as outlines over the snapped notes
+fn draw_export_preview(
+    mut commands: Commands,
+    presses: Res<Presses>,
+    song: Res<MapMakerSong>,
+    view: Res<TimelineView>,
+    materials: Res<TimelineMaterials>,
+    arrow_materials: Res<ArrowMaterialResource>,
+    query: Query<Entity, With<TimelineSprite>>,
+) {
+    if !song.is_changed() && !view.is_changed() {
+        return;
+    }
+
+    clear_timeline(&mut commands, &query);
+    spawn_timeline_grid(&mut commands, &view, &materials, Some(song.beat_grid()));
+
+    for arrow in presses.snapped(song.beat_grid(), song.subdivision).iter() {
+        if view.is_visible(arrow.click_time) {
+            let material = arrow_materials.speed_material(arrow.speed);
+            spawn_timeline_note(
+                &mut commands,
+                &view,
+                arrow,
+                material,
+                TIMELINE_NOTE_SIZE,
+                1.,
+            );
+        }
+    }
+    for arrow in presses.arrows.iter() {
+        if view.is_visible(arrow.click_time) {
+            let material = arrow_materials.border_material();
+            spawn_timeline_note(
+                &mut commands,
+                &view,
+                arrow,
+                material,
+                TIMELINE_NOTE_SIZE,
+                1.1,
+            );
+        }
+    }
+}
+
+struct ExportText;
+fn setup_export_text(mut commands: Commands, materials: Res<TimelineMaterials>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    top: Val::Px(10.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font_size: 20.0,
+                    font: materials.font.clone(),
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(ExportText);
+}
+
+fn update_export_text(song: Res<MapMakerSong>, mut query: Query<&mut Text, With<ExportText>>) {
+    if !song.is_changed() {
+        return;
+    }
+
+    let snap = song.subdivision.map(|sub| sub.name()).unwrap_or("none");
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "BPM: {:.1} (Up/Down). Offset: {:.3}s (Left/Right). Snap: {} (0-5)\n\
+            Outlines are the recorded presses. Enter: export",
+            song.bpm, song.offset, snap,
+        );
+    }
+}
+
+/// Saves the snapped chart and goes back to the menu when Enter is pressed
+fn export_map(
+    keyboard_input: Res<Input<KeyCode>>,
+    presses: Res<Presses>,
+    song: Res<MapMakerSong>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        save_chart(&presses, &song);
+        state
+            .set(AppState::Menu)
+            .expect("Couldn't switch state to Menu");
+    }
+}
+
+fn despawn_export_preview(
+    mut commands: Commands,
+    sprites: Query<Entity, With<TimelineSprite>>,
+    texts: Query<Entity, With<ExportText>>,
+) {
+    for entity in sprites.iter().chain(texts.iter()) {
+        commands.entity(entity).despawn();
     }
 }
 
@@ -51,8 +320,13 @@ fn setup_map_maker_arrows(
     mut commands: Commands,
     mut materials: ResMut<Assets<ColorMaterial>>,
     asset_server: ResMut<AssetServer>,
+    config: Res<RhythmConfig>,
 ) {
-    let border_handle = materials.add(asset_server.load("images/arrow_border.png").into());
+    let border_handle = materials.add(
+        asset_server
+            .load(config.assets.arrow_border.as_str())
+            .into(),
+    );
 
     let directions = [Up, Down, Left, Right];
     for direction in directions.iter() {
@@ -79,41 +353,69 @@ fn setup_map_maker_arrows(
 fn toggle_map_maker_arrows(
     mut query: Query<(&mut Visible, &MapMakerArrow)>,
     keyboard_input: Res<Input<KeyCode>>,
+    config: Res<RhythmConfig>,
 ) {
     for (mut visible, arrow) in query.iter_mut() {
-        visible.is_visible = arrow.0.key_pressed(&keyboard_input);
+        visible.is_visible = config.keys.pressed(arrow.0, &keyboard_input);
     }
 }
 
-struct MapMakerAudio(Handle<AudioSource>);
-impl FromWorld for MapMakerAudio {
-    fn from_world(world: &mut World) -> Self {
-        let world = world.cell();
-
-        let asset_server = world.get_resource_mut::<AssetServer>().unwrap();
-        let audio = asset_server.load("map_maker_song.mp3");
-        Self(audio)
+fn despawn_map_maker_arrows(mut commands: Commands, query: Query<Entity, With<MapMakerArrow>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
     }
 }
-fn start_song(audio: Res<Audio>, map_maker_audio: Res<MapMakerAudio>) {
-    audio.play(map_maker_audio.0.clone());
+
+fn load_song(
+    asset_server: Res<AssetServer>,
+    song: Res<MapMakerSong>,
+    mut playback: ResMut<Playback>,
+) {
+    if let Some(filename) = &song.filename {
+        // Song starts 3 seconds after real time, same as in the game
+        playback.load(&asset_server, filename, SONG_START_DELAY, true);
+    }
 }
 
 pub struct MapMakerPlugin;
 impl Plugin for MapMakerPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.init_resource::<Presses>()
-            .init_resource::<MapMakerAudio>()
+            .init_resource::<MapMakerSong>()
             .add_system_set(
                 SystemSet::on_enter(AppState::MakeMap)
                     .with_system(setup_map_maker_arrows.system())
-                    .with_system(start_song.system()),
+                    .with_system(reset_presses.system())
+                    .with_system(load_song.system()),
             )
             .add_system_set(
-                SystemSet::on_update(AppState::Game)
+                SystemSet::on_update(AppState::MakeMap)
                     .with_system(toggle_map_maker_arrows.system())
                     .with_system(save_to_file_on_exit.system())
-                    .with_system(save_key_presses.system()),
+                    .with_system(save_key_presses.system())
+                    .with_system(finish_map.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::MakeMap)
+                    .with_system(despawn_map_maker_arrows.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::ExportMap)
+                    .with_system(reset_timeline.system())
+                    .with_system(setup_export_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::ExportMap)
+                    .with_system(scroll_timeline.system())
+                    .with_system(adjust_quantization.system())
+                    .with_system(draw_export_preview.system())
+                    .with_system(update_export_text.system())
+                    .with_system(save_to_file_on_exit.system())
+                    .with_system(export_map.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::ExportMap)
+                    .with_system(despawn_export_preview.system()),
             );
     }
 }
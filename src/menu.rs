@@ -1,13 +1,22 @@
+use crate::config::RhythmConfig;
 use crate::consts::*;
-use crate::types::load_config;
-use bevy::prelude::*;
+use crate::editor::EditorChart;
+use crate::generator::{decode_song_file, difficulty_stem, generate_chart, seed_from_name};
+use crate::high_scores::LastPlay;
+use crate::library;
+use crate::map_maker::MapMakerSong;
+use crate::replay::{CurrentReplay, WatchReplay};
+use crate::settings::{Settings, Volume};
+use crate::sfx::{PlaySfx, Sfx};
+use crate::types::{parse_config, unused_chart_stem, write_config};
+use bevy::{ecs::component::Component, prelude::*, window::ReceivedCharacter};
 
-struct ButtonMaterials {
-    none: Handle<ColorMaterial>,
-    normal: Handle<ColorMaterial>,
-    hovered: Handle<ColorMaterial>,
-    pressed: Handle<ColorMaterial>,
-    font: Handle<Font>,
+pub struct ButtonMaterials {
+    pub none: Handle<ColorMaterial>,
+    pub normal: Handle<ColorMaterial>,
+    pub hovered: Handle<ColorMaterial>,
+    pub pressed: Handle<ColorMaterial>,
+    pub font: Handle<Font>,
 }
 
 impl FromWorld for ButtonMaterials {
@@ -16,38 +25,58 @@ impl FromWorld for ButtonMaterials {
 
         let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
         let asset_server = world.get_resource_mut::<AssetServer>().unwrap();
+        let config = world.get_resource::<RhythmConfig>().unwrap();
         ButtonMaterials {
             none: materials.add(Color::NONE.into()),
             normal: materials.add(Color::rgb(0.15, 0.15, 0.15).into()),
             hovered: materials.add(Color::rgb(0.25, 0.25, 0.25).into()),
             pressed: materials.add(Color::rgb(0.35, 0.75, 0.35).into()),
-            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+            font: asset_server.load(config.assets.font.as_str()),
         }
     }
 }
 
 enum MenuButton {
     MakeMap,
-    PlaySong(String),
+    PickAudio(String),
+    PickChart(String),
+    EditChart(String),
+    StartMap,
+    Settings,
+    VolumeDown(Volume),
+    VolumeUp(Volume),
+    ChangeDifficulty,
+    GenerateChart,
+    WatchReplay,
+    Back,
 }
 impl MenuButton {
     fn name(&self) -> String {
         match self {
             Self::MakeMap => "Make map".to_string(),
-            Self::PlaySong(song) => format!("Play song: {}", song),
+            Self::PickAudio(filename) => format!("Audio: {}", filename),
+            Self::PickChart(song) => format!("Chart: {}", song),
+            Self::EditChart(song) => format!("Edit chart: {}", song),
+            Self::StartMap => "Start recording".to_string(),
+            Self::Settings => "Settings".to_string(),
+            Self::VolumeDown(volume) => format!("{} volume -", volume.name()),
+            Self::VolumeUp(volume) => format!("{} volume +", volume.name()),
+            Self::ChangeDifficulty => "Change difficulty".to_string(),
+            Self::GenerateChart => "Generate draft chart".to_string(),
+            Self::WatchReplay => "Watch replay".to_string(),
+            Self::Back => "Back".to_string(),
         }
     }
 }
 
 struct MenuUI;
-fn setup_menu(mut commands: Commands, button_materials: Res<ButtonMaterials>) {
-    // Make list of buttons
-    let mut buttons: Vec<MenuButton> = get_songs()
-        .iter()
-        .map(|name| MenuButton::PlaySong(name.clone()))
-        .collect();
-    buttons.push(MenuButton::MakeMap);
 
+/// Spawns the root node of a menu, with a button for each of `buttons`
+fn spawn_menu(
+    commands: &mut Commands,
+    button_materials: &ButtonMaterials,
+    buttons: Vec<MenuButton>,
+) -> Entity {
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
@@ -94,7 +123,201 @@ fn setup_menu(mut commands: Commands, button_materials: Res<ButtonMaterials>) {
                     })
                     .insert(button);
             }
-        });
+        })
+        .id()
+}
+
+/// Adds a text above the buttons of a menu, with `marker` to find it
+fn spawn_menu_header(
+    commands: &mut Commands,
+    button_materials: &ButtonMaterials,
+    menu: Entity,
+    marker: impl Component,
+) -> Entity {
+    // Children are laid out from the bottom, so the text goes last to show up on top
+    let text = commands
+        .spawn_bundle(TextBundle {
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: button_materials.font.clone(),
+                    font_size: 30.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+                Default::default(),
+            ),
+            style: Style {
+                margin: Rect::all(Val::Px(10.)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(marker)
+        .id();
+    commands.entity(menu).push_children(&[text]);
+    text
+}
+
+fn setup_menu(mut commands: Commands, button_materials: Res<ButtonMaterials>) {
+    let buttons = vec![MenuButton::MakeMap, MenuButton::Settings];
+    let menu = spawn_menu(&mut commands, &button_materials, buttons);
+
+    // The song list takes the rest of the screen, so keep the buttons in the bottom right corner
+    commands.entity(menu).insert(Style {
+        position_type: PositionType::Absolute,
+        position: Rect {
+            right: Val::Px(10.),
+            bottom: Val::Px(10.),
+            ..Default::default()
+        },
+        size: Size::new(Val::Px(350.), Val::Px(150.)),
+        flex_direction: FlexDirection::Column,
+        ..Default::default()
+    });
+}
+
+/// Text showing the song name being typed and the picked audio
+struct SongPickerText;
+
+fn setup_song_picker(
+    mut commands: Commands,
+    button_materials: Res<ButtonMaterials>,
+    mut song: ResMut<MapMakerSong>,
+) {
+    *song = MapMakerSong::default();
+
+    // Make list of buttons
+    let mut buttons: Vec<MenuButton> = library::find_audio()
+        .into_iter()
+        .map(MenuButton::PickAudio)
+        .collect();
+    // Charts in zipped packs can't be saved, so they can't be edited
+    let charts = library::find_charts()
+        .into_iter()
+        .filter(|chart| !library::is_archived(chart));
+    for chart in charts {
+        buttons.push(MenuButton::PickChart(chart.clone()));
+        buttons.push(MenuButton::EditChart(chart));
+    }
+    buttons.push(MenuButton::StartMap);
+    buttons.push(MenuButton::ChangeDifficulty);
+    buttons.push(MenuButton::GenerateChart);
+    buttons.push(MenuButton::Back);
+
+    let menu = spawn_menu(&mut commands, &button_materials, buttons);
+    let text = spawn_menu_header(&mut commands, &button_materials, menu, SongPickerText);
+    commands.entity(text).insert(TextField);
+}
+
+/// Writes the typed characters into the song name
+fn type_song_name(
+    mut char_events: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut song: ResMut<MapMakerSong>,
+) {
+    for event in char_events.iter() {
+        if !event.char.is_control() {
+            song.name.push(event.char);
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        song.name.pop();
+    }
+}
+
+fn update_song_picker_text(
+    song: Res<MapMakerSong>,
+    mut query: Query<&mut Text, With<SongPickerText>>,
+) {
+    if !song.is_changed() {
+        return;
+    }
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "Song name: {}_\nAudio: {}\nGenerator difficulty: {}",
+            song.name,
+            song.filename.as_deref().unwrap_or("none"),
+            song.difficulty.name()
+        );
+        if let Some(error) = &song.error {
+            text.sections[0].value += &format!("\n{}", error);
+        }
+    }
+}
+
+/// Text showing the current volumes
+struct SettingsText;
+
+fn setup_settings_menu(mut commands: Commands, button_materials: Res<ButtonMaterials>) {
+    let mut buttons = vec![];
+    let volumes = [Volume::Master, Volume::Music, Volume::Sfx];
+    for volume in volumes.iter() {
+        buttons.push(MenuButton::VolumeDown(*volume));
+        buttons.push(MenuButton::VolumeUp(*volume));
+    }
+    buttons.push(MenuButton::Back);
+
+    let menu = spawn_menu(&mut commands, &button_materials, buttons);
+    spawn_menu_header(&mut commands, &button_materials, menu, SettingsText);
+}
+
+fn update_settings_text(settings: Res<Settings>, mut query: Query<&mut Text, With<SettingsText>>) {
+    // The text is empty until the first update, so it can't wait for a change
+    let volumes = [Volume::Master, Volume::Music, Volume::Sfx];
+    for mut text in query.iter_mut() {
+        text.sections[0].value = volumes
+            .iter()
+            .map(|volume| {
+                format!(
+                    "{} volume: {:.0}%",
+                    volume.name(),
+                    settings.volume(*volume) * 100.
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+}
+
+/// Text showing how the last song went
+struct ResultsText;
+
+fn setup_results_menu(mut commands: Commands, button_materials: Res<ButtonMaterials>) {
+    let menu = spawn_menu(
+        &mut commands,
+        &button_materials,
+        vec![MenuButton::WatchReplay, MenuButton::Back],
+    );
+    spawn_menu_header(&mut commands, &button_materials, menu, ResultsText);
+}
+
+fn update_results_text(last_play: Res<LastPlay>, mut query: Query<&mut Text, With<ResultsText>>) {
+    let play = &last_play.play;
+    let record = |is_record: bool| if is_record { "  NEW RECORD" } else { "" };
+    let replay = match last_play.replay_matches {
+        Some(true) => "Replay: same score as recorded\n",
+        Some(false) => "Replay: score differs from the recording\n",
+        None => "",
+    };
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "{}\n{}Score: {}{}\nAccuracy: {:.2}%{}\nMax combo: {}{}\nPerfect: {}. Great: {}. Good: {}. Miss: {}",
+            last_play.title,
+            replay,
+            play.score,
+            record(last_play.is_record(|play| play.score)),
+            play.accuracy * 100.,
+            record(last_play.is_record(|play| play.accuracy)),
+            play.max_combo,
+            record(last_play.is_record(|play| play.max_combo)),
+            play.perfect,
+            play.great,
+            play.good,
+            play.miss,
+        );
+    }
 }
 
 fn despawn_menu(mut commands: Commands, query: Query<(Entity, &MenuUI)>) {
@@ -106,81 +329,362 @@ fn despawn_menu(mut commands: Commands, query: Query<(Entity, &MenuUI)>) {
 fn button_color_system(
     button_materials: Res<ButtonMaterials>,
     mut query: Query<
-        (&Interaction, &mut Handle<ColorMaterial>),
-        (Changed<Interaction>, With<Button>),
+        (&Interaction, Option<&Focused>, &mut Handle<ColorMaterial>),
+        With<MenuButton>,
     >,
 ) {
-    for (interaction, mut material) in query.iter_mut() {
-        match *interaction {
-            Interaction::Clicked => {
+    for (interaction, focused, mut material) in query.iter_mut() {
+        match (*interaction, focused.is_some()) {
+            (Interaction::Clicked, _) => {
                 *material = button_materials.pressed.clone();
             }
-            Interaction::Hovered => {
+            (Interaction::Hovered, _) | (Interaction::None, true) => {
                 *material = button_materials.hovered.clone();
             }
-            Interaction::None => {
+            (Interaction::None, false) => {
                 *material = button_materials.normal.clone();
             }
         }
     }
 }
 
-fn button_press_system(
+/// Button that gets pressed with the keyboard or a gamepad
+struct Focused;
+
+/// Marks a text that's typed into, so letters, space and backspace don't move the focus
+struct TextField;
+
+/// Event to press a menu button, same as clicking it
+struct PressMenuButton(Entity);
+
+/// Moves the focus with the arrow keys, the lane keys or a gamepad, and presses the focused button
+fn button_focus_system(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    query: Query<(&Interaction, &MenuButton), (Changed<Interaction>, With<Button>)>,
-    mut state: ResMut<State<AppState>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    buttons: Query<(Entity, &GlobalTransform, &MenuButton, Option<&Focused>)>,
+    text_fields: Query<(), With<TextField>>,
+    mut press: EventWriter<PressMenuButton>,
+    mut sfx: EventWriter<PlaySfx>,
 ) {
-    for (interaction, button) in query.iter() {
-        if *interaction == Interaction::Clicked {
-            match button {
-                MenuButton::MakeMap => state
-                    .set(AppState::MakeMap)
-                    .expect("Couldn't switch state to MakeMap"),
-                MenuButton::PlaySong(song) => {
-                    let config = load_config(&*format!("{}.toml", song), &asset_server);
-                    commands.insert_resource(config);
-                    state
-                        .set(AppState::Game)
-                        .expect("Couldn't switch state to Game")
-                }
-            };
+    let typing = text_fields.iter().next().is_some();
+    let pressed = |keys: &[KeyCode], typed: &[KeyCode]| {
+        keys.iter().any(|key| keyboard_input.just_pressed(*key))
+            || (!typing && typed.iter().any(|key| keyboard_input.just_pressed(*key)))
+    };
+    let gamepad_pressed = |button_type: GamepadButtonType| {
+        gamepad_input
+            .get_just_pressed()
+            .any(|button| button.1 == button_type)
+    };
+
+    // Buttons in reading order, from the top left
+    let mut order: Vec<_> = buttons.iter().collect();
+    order.sort_by(|(_, a, _, _), (_, b, _, _)| {
+        let (a, b) = (a.translation, b.translation);
+        b.y.partial_cmp(&a.y)
+            .unwrap()
+            .then(a.x.partial_cmp(&b.x).unwrap())
+    });
+    let focused = order
+        .iter()
+        .position(|(_, _, _, focused)| focused.is_some());
+
+    let mut change = 0;
+    if pressed(&[KeyCode::Up, KeyCode::Left], &[KeyCode::D, KeyCode::J])
+        || gamepad_pressed(GamepadButtonType::DPadUp)
+        || gamepad_pressed(GamepadButtonType::DPadLeft)
+    {
+        change -= 1;
+    }
+    if pressed(&[KeyCode::Down, KeyCode::Right], &[KeyCode::F, KeyCode::K])
+        || gamepad_pressed(GamepadButtonType::DPadDown)
+        || gamepad_pressed(GamepadButtonType::DPadRight)
+    {
+        change += 1;
+    }
+
+    if change != 0 && !order.is_empty() {
+        let new_focus = match focused {
+            Some(focused) => (focused as isize + change).rem_euclid(order.len() as isize) as usize,
+            // The first press focuses the first or last button
+            None if change > 0 => 0,
+            None => order.len() - 1,
+        };
+        if let Some(focused) = focused {
+            commands.entity(order[focused].0).remove::<Focused>();
+        }
+        commands.entity(order[new_focus].0).insert(Focused);
+        sfx.send(PlaySfx(Sfx::MenuMove));
+    }
+
+    if pressed(&[KeyCode::Return], &[KeyCode::Space]) || gamepad_pressed(GamepadButtonType::South) {
+        if let Some(focused) = focused {
+            press.send(PressMenuButton(order[focused].0));
+            sfx.send(PlaySfx(Sfx::MenuConfirm));
+        }
+    }
+    if pressed(&[], &[KeyCode::Back]) || gamepad_pressed(GamepadButtonType::East) {
+        let back = order
+            .iter()
+            .find(|(_, _, button, _)| matches!(button, MenuButton::Back));
+        if let Some((back, ..)) = back {
+            press.send(PressMenuButton(*back));
+            sfx.send(PlaySfx(Sfx::MenuConfirm));
         }
     }
 }
 
-use std::fs::read_dir;
-pub fn get_songs() -> Vec<String> {
-    let paths = read_dir("assets/songs").unwrap();
+/// Focuses the button under the mouse, so only one button is highlighted
+fn focus_hovered_button(
+    mut commands: Commands,
+    hovered: Query<(Entity, &Interaction, &MenuButton), Changed<Interaction>>,
+    focused: Query<Entity, With<Focused>>,
+) {
+    for (entity, interaction, _) in hovered.iter() {
+        if *interaction == Interaction::None {
+            continue;
+        }
+        for focused in focused.iter() {
+            commands.entity(focused).remove::<Focused>();
+        }
+        commands.entity(entity).insert(Focused);
+    }
+}
 
-    let mut vec = vec![];
-    for path in paths {
-        let path = path.unwrap().path();
+/// Opens the map maker and the settings from the song select, which uses the arrow keys itself
+fn menu_shortcuts(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    buttons: Query<(Entity, &MenuButton)>,
+    mut press: EventWriter<PressMenuButton>,
+    mut sfx: EventWriter<PlaySfx>,
+) {
+    let gamepad_pressed = |button_type: GamepadButtonType| {
+        gamepad_input
+            .get_just_pressed()
+            .any(|button| button.1 == button_type)
+    };
+    let shortcut = |button: &MenuButton| match button {
+        MenuButton::Settings => {
+            keyboard_input.just_pressed(KeyCode::F1) || gamepad_pressed(GamepadButtonType::Start)
+        }
+        MenuButton::MakeMap => {
+            keyboard_input.just_pressed(KeyCode::F2) || gamepad_pressed(GamepadButtonType::Select)
+        }
+        _ => false,
+    };
 
-        if "toml" == path.as_path().extension().unwrap() {
-            vec.push(
-                path.as_path()
-                    .file_stem()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string(),
-            );
+    if let Some((entity, _)) = buttons.iter().find(|(_, button)| shortcut(button)) {
+        press.send(PressMenuButton(entity));
+        sfx.send(PlaySfx(Sfx::MenuConfirm));
+    }
+}
+
+/// Plays a sound when a button is hovered or clicked
+fn button_sound_system(
+    query: Query<&Interaction, (Changed<Interaction>, With<Button>)>,
+    mut sfx: EventWriter<PlaySfx>,
+) {
+    for interaction in query.iter() {
+        match *interaction {
+            Interaction::Clicked => sfx.send(PlaySfx(Sfx::MenuConfirm)),
+            Interaction::Hovered => sfx.send(PlaySfx(Sfx::MenuMove)),
+            Interaction::None => {}
+        }
+    }
+}
+
+/// Returns the menu buttons that were clicked or pressed with the keyboard or a gamepad
+fn pressed_buttons<'a>(
+    clicks: &Query<(Entity, &Interaction), Changed<Interaction>>,
+    buttons: &'a Query<&MenuButton>,
+    press_events: &mut EventReader<PressMenuButton>,
+) -> Vec<&'a MenuButton> {
+    let clicked = clicks
+        .iter()
+        .filter(|(_, interaction)| **interaction == Interaction::Clicked)
+        .map(|(entity, _)| entity);
+    let pressed = press_events.iter().map(|PressMenuButton(entity)| *entity);
+    // Clicks on other buttons, like the song list, aren't menu buttons
+    let entities: Vec<Entity> = clicked.chain(pressed).collect();
+    entities
+        .iter()
+        .filter_map(|entity| buttons.get(*entity).ok())
+        .collect()
+}
+
+fn button_press_system(
+    clicks: Query<(Entity, &Interaction), Changed<Interaction>>,
+    buttons: Query<&MenuButton>,
+    mut press_events: EventReader<PressMenuButton>,
+    mut state: ResMut<State<AppState>>,
+    mut song: ResMut<MapMakerSong>,
+    mut editor_chart: ResMut<EditorChart>,
+    mut settings: ResMut<Settings>,
+) {
+    for button in pressed_buttons(&clicks, &buttons, &mut press_events) {
+        if song.error.is_some() {
+            song.error = None;
+        }
+        match button {
+            MenuButton::MakeMap => state
+                .set(AppState::PickSong)
+                .expect("Couldn't switch state to PickSong"),
+            MenuButton::PickAudio(filename) => song.filename = Some(filename.clone()),
+            MenuButton::PickChart(chart) => {
+                let config = match parse_config(&format!("{}.toml", chart)) {
+                    Ok(config) => config,
+                    Err(error) => {
+                        song.error = Some(format!("Couldn't load {}: {}", chart, error));
+                        continue;
+                    }
+                };
+                if let Some(grid) = config.beat_grid() {
+                    song.bpm = grid.bpm;
+                    song.offset = grid.offset;
+                }
+                song.name = config.name;
+                song.filename = Some(library::resolve(chart, &config.filename));
+            }
+            MenuButton::EditChart(chart) => {
+                if let Err(error) = editor_chart.load(chart) {
+                    song.error = Some(format!("Couldn't load {}: {}", chart, error));
+                    continue;
+                }
+                state
+                    .set(AppState::EditMap)
+                    .expect("Couldn't switch state to EditMap")
+            }
+            MenuButton::StartMap => {
+                if song.is_ready() {
+                    state
+                        .set(AppState::MakeMap)
+                        .expect("Couldn't switch state to MakeMap")
+                }
+            }
+            MenuButton::Settings => state
+                .set(AppState::Settings)
+                .expect("Couldn't switch state to Settings"),
+            MenuButton::VolumeDown(volume) => settings.change_volume(*volume, -0.1),
+            MenuButton::VolumeUp(volume) => settings.change_volume(*volume, 0.1),
+            MenuButton::ChangeDifficulty => song.difficulty = song.difficulty.next(),
+            MenuButton::GenerateChart => {
+                if let (true, Some(filename)) = (song.is_ready(), song.filename.clone()) {
+                    let stem = difficulty_stem(&song.chart_stem(), song.difficulty);
+                    let decoded = match decode_song_file(&filename) {
+                        Some(decoded) => decoded,
+                        None => {
+                            song.error = Some(format!("Couldn't decode {}", filename));
+                            continue;
+                        }
+                    };
+                    let config = generate_chart(
+                        &decoded,
+                        song.name.trim(),
+                        &filename,
+                        None,
+                        song.difficulty,
+                        song.difficulty.settings(),
+                        seed_from_name(&stem),
+                    );
+                    let stem = unused_chart_stem(&stem);
+                    write_config(&format!("{}.toml", stem), &config);
+
+                    // Open the draft in the editor to polish it
+                    if let Err(error) = editor_chart.load(&stem) {
+                        song.error = Some(format!("Couldn't load {}: {}", stem, error));
+                        continue;
+                    }
+                    state
+                        .set(AppState::EditMap)
+                        .expect("Couldn't switch state to EditMap")
+                }
+            }
+            // Handled by `watch_replay_button`
+            MenuButton::WatchReplay => {}
+            MenuButton::Back => state
+                .set(AppState::Menu)
+                .expect("Couldn't switch state to Menu"),
+        };
+    }
+}
+
+/// Watches the replay of the play shown in the results screen
+fn watch_replay_button(
+    clicks: Query<(Entity, &Interaction), Changed<Interaction>>,
+    buttons: Query<&MenuButton>,
+    mut press_events: EventReader<PressMenuButton>,
+    current: Res<CurrentReplay>,
+    mut watch: EventWriter<WatchReplay>,
+) {
+    for button in pressed_buttons(&clicks, &buttons, &mut press_events) {
+        if let MenuButton::WatchReplay = button {
+            watch.send(WatchReplay(current.replay.clone()));
         }
     }
-    vec
 }
 
 pub struct MenuPlugin;
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.init_resource::<ButtonMaterials>()
+            .add_event::<PressMenuButton>()
             .add_system_set(SystemSet::on_enter(AppState::Menu).with_system(setup_menu.system()))
             .add_system_set(
                 SystemSet::on_update(AppState::Menu)
+                    .with_system(menu_shortcuts.system())
                     .with_system(button_color_system.system())
+                    .with_system(button_sound_system.system())
                     .with_system(button_press_system.system()),
             )
-            .add_system_set(SystemSet::on_exit(AppState::Menu).with_system(despawn_menu.system()));
+            .add_system_set(SystemSet::on_exit(AppState::Menu).with_system(despawn_menu.system()))
+            .add_system_set(
+                SystemSet::on_enter(AppState::PickSong).with_system(setup_song_picker.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::PickSong)
+                    .with_system(button_focus_system.system())
+                    .with_system(focus_hovered_button.system())
+                    .with_system(button_color_system.system())
+                    .with_system(button_sound_system.system())
+                    .with_system(button_press_system.system())
+                    .with_system(type_song_name.system())
+                    .with_system(update_song_picker_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::PickSong).with_system(despawn_menu.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::Settings).with_system(setup_settings_menu.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Settings)
+                    .with_system(button_focus_system.system())
+                    .with_system(focus_hovered_button.system())
+                    .with_system(button_color_system.system())
+                    .with_system(button_sound_system.system())
+                    .with_system(button_press_system.system())
+                    .with_system(update_settings_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Settings).with_system(despawn_menu.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::Results).with_system(setup_results_menu.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Results)
+                    .with_system(button_focus_system.system())
+                    .with_system(focus_hovered_button.system())
+                    .with_system(button_color_system.system())
+                    .with_system(button_sound_system.system())
+                    .with_system(button_press_system.system())
+                    .with_system(watch_replay_button.system())
+                    .with_system(update_results_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Results).with_system(despawn_menu.system()),
+            );
     }
 }
@@ -0,0 +1,255 @@
+use crate::config::RhythmConfig;
+use crate::consts::*;
+use crate::editor::EditorChart;
+use crate::map_maker::MapMakerSong;
+use crate::playback::Playback;
+use crate::sfx::{PlaySfx, Sfx};
+use crate::time::ControlledTime;
+use crate::types::{BeatGrid, SongConfig};
+use bevy::{
+    input::{keyboard::KeyCode, Input},
+    prelude::*,
+};
+
+/// Longest time between two frames that still plays the ticks in between.
+/// Anything longer is a seek, and playing every tick skipped over would be noise
+const MAX_TICK_GAP: f64 = 0.25;
+
+/// Settings and state for the metronome and the assist ticks
+pub struct Metronome {
+    /// Click on every beat
+    pub enabled: bool,
+    /// Tick on every note's `click_time`
+    pub assist: bool,
+    /// BPM used when the chart doesn't have one
+    pub manual_bpm: f64,
+    /// Song time when the ticks were last checked
+    last_time: Option<f64>,
+}
+impl Default for Metronome {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            assist: false,
+            manual_bpm: 120.,
+            last_time: None,
+        }
+    }
+}
+impl Metronome {
+    /// Returns the song times passed since the last call, if the song moved forward normally
+    fn advance(&mut self, now: f64) -> Option<(f64, f64)> {
+        let last = self.last_time.replace(now)?;
+        if now > last && now - last < MAX_TICK_GAP {
+            Some((last, now))
+        } else {
+            None
+        }
+    }
+
+    fn manual_grid(&self) -> BeatGrid {
+        BeatGrid {
+            bpm: self.manual_bpm,
+            offset: 0.,
+        }
+    }
+}
+
+/// Plays the metronome for every beat, and the assist tick for every note, between two song times
+fn play_ticks(
+    sfx: &mut EventWriter<PlaySfx>,
+    metronome: &Metronome,
+    (from, to): (f64, f64),
+    grid: BeatGrid,
+    mut click_times: impl Iterator<Item = f64>,
+) {
+    if metronome.enabled {
+        let beat = grid.beat_at(to).floor();
+        if grid.beat_at(from) < beat && beat >= 0. {
+            let sound = if beat as i64 % 4 == 0 {
+                Sfx::Downbeat
+            } else {
+                Sfx::Beat
+            };
+            sfx.send(PlaySfx(sound));
+        }
+    }
+
+    if metronome.assist && click_times.any(|time| from < time && time <= to) {
+        sfx.send(PlaySfx(Sfx::AssistTick));
+    }
+}
+
+/// Turns the metronome on and off with M, the assist ticks with N, and changes the manual BPM
+fn metronome_controls(keyboard_input: Res<Input<KeyCode>>, mut metronome: ResMut<Metronome>) {
+    if keyboard_input.just_pressed(KeyCode::M) {
+        metronome.enabled = !metronome.enabled;
+    }
+    if keyboard_input.just_pressed(KeyCode::N) {
+        metronome.assist = !metronome.assist;
+    }
+    if keyboard_input.just_pressed(KeyCode::PageUp) {
+        metronome.manual_bpm = (metronome.manual_bpm + 1.).min(400.);
+    }
+    if keyboard_input.just_pressed(KeyCode::PageDown) {
+        metronome.manual_bpm = (metronome.manual_bpm - 1.).max(20.);
+    }
+}
+
+fn reset_metronome(mut metronome: ResMut<Metronome>) {
+    metronome.last_time = None;
+}
+
+fn game_ticks(
+    mut sfx: EventWriter<PlaySfx>,
+    time: Res<ControlledTime>,
+    config: Res<SongConfig>,
+    mut metronome: ResMut<Metronome>,
+) {
+    // Song starts 3 seconds after real time
+    let song_time = time.seconds_since_startup() - SONG_START_DELAY;
+    if let Some(span) = metronome.advance(song_time) {
+        let grid = config.beat_grid.unwrap_or_else(|| metronome.manual_grid());
+        let click_times = config.click_times.iter().copied();
+        play_ticks(&mut sfx, &metronome, span, grid, click_times);
+    }
+}
+
+/// Starts the map maker's metronome at the BPM picked in the song picker
+fn setup_map_maker_metronome(song: Res<MapMakerSong>, mut metronome: ResMut<Metronome>) {
+    metronome.manual_bpm = song.bpm;
+    metronome.last_time = None;
+}
+
+/// The map maker only has the metronome, since the notes are being recorded.
+/// Its BPM is kept in the song, so the export snaps to the BPM the notes were played to
+fn map_maker_ticks(
+    mut sfx: EventWriter<PlaySfx>,
+    time: Res<ControlledTime>,
+    playback: Res<Playback>,
+    mut song: ResMut<MapMakerSong>,
+    mut metronome: ResMut<Metronome>,
+) {
+    if (song.bpm - metronome.manual_bpm).abs() > f64::EPSILON {
+        song.bpm = metronome.manual_bpm;
+    }
+    if time.is_paused() {
+        metronome.last_time = None;
+        return;
+    }
+    if let Some(span) = metronome.advance(playback.song_time(&time)) {
+        play_ticks(
+            &mut sfx,
+            &metronome,
+            span,
+            song.beat_grid(),
+            std::iter::empty(),
+        );
+    }
+}
+
+fn editor_ticks(
+    mut sfx: EventWriter<PlaySfx>,
+    time: Res<ControlledTime>,
+    playback: Res<Playback>,
+    chart: Res<EditorChart>,
+    mut metronome: ResMut<Metronome>,
+) {
+    if time.is_paused() {
+        metronome.last_time = None;
+        return;
+    }
+    if let Some(span) = metronome.advance(playback.song_time(&time)) {
+        let grid = chart.beat_grid().unwrap_or_else(|| metronome.manual_grid());
+        let click_times = chart.arrows().iter().map(|arrow| arrow.click_time);
+        play_ticks(&mut sfx, &metronome, span, grid, click_times);
+    }
+}
+
+struct MetronomeText;
+fn setup_metronome_text(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<RhythmConfig>,
+) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    top: Val::Px(10.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font_size: 20.0,
+                    font: asset_server.load(config.assets.font.as_str()),
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(MetronomeText);
+}
+
+fn update_metronome_text(
+    metronome: Res<Metronome>,
+    mut query: Query<&mut Text, With<MetronomeText>>,
+) {
+    let on_off = |on: bool| if on { "on" } else { "off" };
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "M: metronome {}. N: assist ticks {}.\nPgUp/PgDn: manual BPM {:.0}",
+            on_off(metronome.enabled),
+            on_off(metronome.assist),
+            metronome.manual_bpm
+        );
+    }
+}
+
+fn despawn_metronome_text(mut commands: Commands, query: Query<Entity, With<MetronomeText>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+pub struct MetronomePlugin;
+impl Plugin for MetronomePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Metronome>();
+
+        let states = [AppState::Game, AppState::MakeMap, AppState::EditMap];
+        for state in states.iter() {
+            app.add_system_set(
+                SystemSet::on_enter(state.clone())
+                    .with_system(reset_metronome.system())
+                    .with_system(setup_metronome_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(state.clone())
+                    .with_system(metronome_controls.system())
+                    .with_system(update_metronome_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(state.clone()).with_system(despawn_metronome_text.system()),
+            );
+        }
+
+        app.add_system_set(SystemSet::on_update(AppState::Game).with_system(game_ticks.system()))
+            .add_system_set(
+                SystemSet::on_enter(AppState::MakeMap)
+                    .with_system(setup_map_maker_metronome.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::MakeMap).with_system(map_maker_ticks.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::EditMap).with_system(editor_ticks.system()),
+            );
+    }
+}
@@ -0,0 +1,191 @@
+use crate::consts::*;
+use crate::library;
+use crate::types::{ArrowTimeToml, Directions, SongConfigToml};
+use std::collections::HashMap;
+
+// osu! beatmaps are `.osu` files with one difficulty each, split in `[Sections]` of `Key: Value` lines
+// or comma separated lists. Only osu!mania beatmaps with 4 keys can be imported, named by their file.
+
+/// Lanes of 4 key beatmaps from left to right, which are pressed with D, F, J and K like in osu!
+const LANES: [Directions; 4] = Directions::ALL;
+
+/// Width of the playfield, which the columns of hit objects are spread across
+const PLAYFIELD_WIDTH: f64 = 512.;
+
+/// Bit of the type of a hit object that makes it a hold note
+const HOLD_NOTE_TYPE: u32 = 128;
+
+/// Checks if a file is an osu! beatmap
+pub fn is_beatmap(file: &str) -> bool {
+    file.ends_with(".osu")
+}
+
+/// Lines of each section of a beatmap, without comments or empty lines
+struct Beatmap<'a> {
+    sections: HashMap<&'a str, Vec<&'a str>>,
+}
+impl<'a> Beatmap<'a> {
+    fn parse(text: &'a str) -> Self {
+        let mut sections: HashMap<&str, Vec<&str>> = HashMap::default();
+        let mut section = "";
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            match line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                Some(name) => section = name,
+                None => sections.entry(section).or_default().push(line),
+            }
+        }
+        Self { sections }
+    }
+
+    fn lines(&self, section: &str) -> &[&'a str] {
+        self.sections.get(section).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Returns the value of a `Key: Value` line
+    fn value(&self, section: &str, key: &str) -> Option<&'a str> {
+        self.lines(section).iter().find_map(|line| {
+            let (line_key, value) = line.split_once(':')?;
+            (line_key.trim() == key).then(|| value.trim())
+        })
+    }
+
+    /// Converts the beatmap into the chart format of the game
+    fn import(&self) -> Result<SongConfigToml, String> {
+        if self.value("General", "Mode") != Some("3") {
+            return Err("Only osu!mania beatmaps can be imported".to_string());
+        }
+        // The key count of osu!mania beatmaps is stored as their circle size
+        let keys = self
+            .value("Difficulty", "CircleSize")
+            .and_then(|keys| keys.parse::<f64>().ok())
+            .ok_or_else(|| "The beatmap has no key count".to_string())?;
+        if keys != LANES.len() as f64 {
+            return Err(format!(
+                "{} key beatmaps can't be imported, the game has {} lanes",
+                keys,
+                LANES.len()
+            ));
+        }
+        let filename = self
+            .value("General", "AudioFilename")
+            .ok_or_else(|| "The beatmap has no AudioFilename".to_string())?;
+
+        // The first uninherited timing point, whose beat length is positive, sets the tempo
+        let timing = self.lines("TimingPoints").iter().find_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let time: f64 = fields.first()?.parse().ok()?;
+            let beat_length: f64 = fields.get(1)?.parse().ok()?;
+            (beat_length > 0.).then(|| (60_000. / beat_length, time / 1000.))
+        });
+
+        let mut arrows = vec![];
+        for line in self.lines("HitObjects") {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let error = || format!("Couldn't parse hit object {}", line);
+            let x: f64 = fields
+                .first()
+                .and_then(|x| x.parse().ok())
+                .ok_or_else(error)?;
+            let time: f64 = fields
+                .get(2)
+                .and_then(|time| time.parse().ok())
+                .ok_or_else(error)?;
+            let kind: u32 = fields
+                .get(3)
+                .and_then(|kind| kind.parse().ok())
+                .ok_or_else(error)?;
+
+            // Hold notes have their end time before the hit sample, like `endTime:0:0:0:0:`
+            let hold_end = if kind & HOLD_NOTE_TYPE != 0 {
+                let end_time: f64 = fields
+                    .get(5)
+                    .and_then(|params| params.split(':').next())
+                    .and_then(|end_time| end_time.parse().ok())
+                    .ok_or_else(error)?;
+                Some(end_time / 1000.)
+            } else {
+                None
+            };
+
+            let column = (x * keys / PLAYFIELD_WIDTH).floor().max(0.) as usize;
+            arrows.push(ArrowTimeToml {
+                click_time: time / 1000.,
+                speed: IMPORTED_SPEED,
+                direction: LANES[column.min(LANES.len() - 1)],
+                hold_end,
+            });
+        }
+
+        Ok(SongConfigToml {
+            format_version: CHART_FORMAT_VERSION,
+            name: self.value("Metadata", "Title").unwrap_or("").to_string(),
+            artist: self.value("Metadata", "Artist").map(str::to_string),
+            difficulty: self.value("Metadata", "Version").map(str::to_string),
+            level: None,
+            filename: filename.to_string(),
+            bpm: timing.map(|(bpm, _)| bpm),
+            offset: timing.map(|(_, offset)| offset),
+            preview_start: self
+                .value("General", "PreviewTime")
+                .and_then(|time| time.parse::<f64>().ok())
+                .filter(|time| *time >= 0.)
+                .map(|time| time / 1000.),
+            arrows,
+        })
+    }
+}
+
+/// Imports a beatmap in `assets/songs`
+pub fn import(file: &str) -> Result<SongConfigToml, String> {
+    let bytes = library::read_file(file).map_err(|error| error.to_string())?;
+    let text = String::from_utf8_lossy(&bytes);
+    Beatmap::parse(text.trim_start_matches('\u{feff}')).import()
+}
+
+/// Returns the beatmap as a chart name. Beatmaps that can't be imported, like ones with other key counts,
+/// are listed too so loading them shows why
+pub fn find_charts(file: &str) -> Vec<String> {
+    vec![file.to_string()]
+}
+
+/// Imports a chart named like `song/song [Hard].osu`, or returns `None` if it isn't named like one
+pub fn import_chart(chart: &str) -> Option<Result<SongConfigToml, String>> {
+    is_beatmap(chart).then(|| import(chart))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hold_notes_end_at_their_end_time() {
+        let text = "osu file format v14
+
+[General]
+AudioFilename: song.mp3
+Mode: 3
+
+[Difficulty]
+CircleSize:4
+
+[HitObjects]
+64,192,1000,1,0,0:0:0:0:
+448,192,1500,128,0,2250:0:0:0:0:
+";
+        let config = Beatmap::parse(text)
+            .import()
+            .expect("Couldn't import beatmap");
+        assert_eq!(config.arrows.len(), 2);
+        assert_eq!(config.arrows[0].direction, LANES[0]);
+        assert_eq!(config.arrows[0].hold_end, None);
+        assert_eq!(config.arrows[1].direction, LANES[3]);
+        assert_eq!(config.arrows[1].click_time, 1.5);
+        assert_eq!(config.arrows[1].hold_end, Some(2.25));
+    }
+}
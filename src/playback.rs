@@ -0,0 +1,569 @@
+use crate::config::RhythmConfig;
+use crate::consts::*;
+use crate::settings::Settings;
+use crate::time::ControlledTime;
+use crate::types::fnv_hash;
+use bevy::{
+    asset::LoadState,
+    input::{keyboard::KeyCode, Input},
+    prelude::*,
+};
+use rodio::{OutputStream, OutputStreamHandle, Sink, Source};
+use std::{
+    fs,
+    io::{self, Cursor},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Samples of a song, decoded so playback can start anywhere
+pub struct DecodedSong {
+    pub channels: u16,
+    pub sample_rate: u32,
+    /// Interleaved samples of all channels
+    pub samples: Arc<[i16]>,
+    /// Hash of the audio file it was decoded from, which the caches made from it are checked against
+    pub source_hash: u64,
+}
+impl DecodedSong {
+    pub fn decode(source: &AudioSource) -> Option<Self> {
+        let decoder = rodio::Decoder::new(Cursor::new(source.clone())).ok()?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let samples: Vec<i16> = decoder.collect();
+
+        Some(Self {
+            channels,
+            sample_rate,
+            samples: samples.into(),
+            source_hash: fnv_hash(&source.bytes),
+        })
+    }
+
+    /// Returns the length of a song in seconds, decoding it without keeping the samples
+    pub fn decode_duration(source: &AudioSource) -> Option<f64> {
+        let decoder = rodio::Decoder::new(Cursor::new(source.clone())).ok()?;
+        let (channels, sample_rate) = (decoder.channels(), decoder.sample_rate());
+        Some(decoder.count() as f64 / channels as f64 / sample_rate as f64)
+    }
+
+    /// Reads the song from the cache, or decodes it and writes it to the cache
+    pub fn load_cached(filename: &str, source: &AudioSource) -> Option<Self> {
+        let path = cache_path(filename, "pcm");
+        if let Some(song) = Self::read_cache(&path, fnv_hash(&source.bytes)) {
+            return Some(song);
+        }
+
+        let song = Self::decode(source)?;
+        // The cache only makes reopening faster, so failing to write it is fine
+        let _ = song.write_cache(&path);
+        Some(song)
+    }
+
+    /// Reads a cached song, if it was decoded from the same file
+    fn read_cache(path: &str, source_hash: u64) -> Option<Self> {
+        let bytes = fs::read(path).ok()?;
+        if bytes.len() < 14 || read_u64(&bytes[0..8]) != source_hash {
+            return None;
+        }
+
+        let channels = u16::from_le_bytes([bytes[8], bytes[9]]);
+        let sample_rate = read_u32(&bytes[10..14]);
+        let samples: Vec<i16> = bytes[14..]
+            .chunks_exact(2)
+            .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect();
+
+        Some(Self {
+            channels,
+            sample_rate,
+            samples: samples.into(),
+            source_hash,
+        })
+    }
+
+    fn write_cache(&self, path: &str) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(14 + self.samples.len() * 2);
+        bytes.extend_from_slice(&self.source_hash.to_le_bytes());
+        bytes.extend_from_slice(&self.channels.to_le_bytes());
+        bytes.extend_from_slice(&self.sample_rate.to_le_bytes());
+        for sample in self.samples.iter() {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+
+        fs::create_dir_all(CACHE_FOLDER)?;
+        fs::write(path, bytes)
+    }
+
+    /// Length of the song in seconds
+    pub fn duration(&self) -> f64 {
+        self.samples.len() as f64 / self.channels as f64 / self.sample_rate as f64
+    }
+
+    /// Returns a source that plays the song from a time in seconds
+    fn source_from(&self, seconds: f64) -> SongSource {
+        let frame = (seconds.max(0.) * self.sample_rate as f64) as usize;
+        SongSource {
+            samples: self.samples.clone(),
+            position: (frame * self.channels as usize).min(self.samples.len()),
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+        }
+    }
+}
+
+/// Returns the path of a file in the cache folder made from an audio file
+pub fn cache_path(filename: &str, extension: &str) -> String {
+    // Songs can be in folders, but the cache is flat
+    format!(
+        "{}/{}.{}",
+        CACHE_FOLDER,
+        filename.replace('/', "_"),
+        extension
+    )
+}
+
+pub fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+pub fn read_u64(bytes: &[u8]) -> u64 {
+    let mut array = [0; 8];
+    array.copy_from_slice(&bytes[0..8]);
+    u64::from_le_bytes(array)
+}
+
+/// Plays a [`DecodedSong`] starting at any sample
+struct SongSource {
+    samples: Arc<[i16]>,
+    position: usize,
+    channels: u16,
+    sample_rate: u32,
+}
+impl Iterator for SongSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+}
+impl Source for SongSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Audio device the players of the game share, since some can only be opened once
+pub struct OutputDevice {
+    /// Output stream and its handle, if there's an audio device
+    output: Option<(OutputStream, OutputStreamHandle)>,
+}
+impl Default for OutputDevice {
+    fn default() -> Self {
+        let output = match OutputStream::try_default() {
+            Ok(output) => Some(output),
+            Err(error) => {
+                warn!(
+                    "Couldn't open the audio device, playing without sound: {}",
+                    error
+                );
+                None
+            }
+        };
+        Self { output }
+    }
+}
+impl OutputDevice {
+    /// Returns a handle to play on the device, opening it if no player has yet
+    pub fn handle(world: &mut World) -> Option<OutputStreamHandle> {
+        if world.get_non_send_resource::<Self>().is_none() {
+            world.insert_non_send(Self::default());
+        }
+        let device = world.get_non_send_resource::<Self>()?;
+        device.output.as_ref().map(|(_, handle)| handle.clone())
+    }
+}
+
+/// Plays songs on the audio device, unlike bevy's `Audio` it can stop them
+pub struct SongPlayer {
+    /// Handle of the audio device, if there is one
+    output: Option<OutputStreamHandle>,
+    sink: Option<Sink>,
+    volume: f32,
+}
+impl FromWorld for SongPlayer {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            output: OutputDevice::handle(world),
+            sink: None,
+            volume: 1.,
+        }
+    }
+}
+impl SongPlayer {
+    /// Stops the current song and returns a new sink to play the next one
+    fn new_sink(&mut self) -> Option<Sink> {
+        self.stop();
+
+        let sink = Sink::try_new(self.output.as_ref()?).ok()?;
+        sink.set_volume(self.volume);
+        Some(sink)
+    }
+
+    /// Plays a song from a time in seconds, negative times wait before starting
+    pub fn play(&mut self, song: &DecodedSong, from: f64, speed: f32) {
+        let sink = match self.new_sink() {
+            Some(sink) => sink,
+            None => return,
+        };
+
+        let source = song.source_from(from).speed(speed);
+        if from < 0. {
+            let delay = Duration::from_secs_f64(-from / speed as f64);
+            sink.append(source.delay(delay));
+        } else {
+            sink.append(source);
+        }
+        self.sink = Some(sink);
+    }
+
+    /// Plays an audio file from the start, without decoding all of it first
+    pub fn play_audio(&mut self, audio: &AudioSource) {
+        let sink = match self.new_sink() {
+            Some(sink) => sink,
+            None => return,
+        };
+
+        match rodio::Decoder::new(Cursor::new(audio.clone())) {
+            Ok(decoder) => sink.append(decoder),
+            Err(error) => warn!("Couldn't decode the song, playing without audio: {}", error),
+        }
+        // Kept even if it has nothing to play, so the song isn't decoded again
+        self.sink = Some(sink);
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        if let Some(sink) = &self.sink {
+            sink.set_volume(volume);
+        }
+    }
+}
+
+/// Song that's being played in the map maker or the chart editor
+#[derive(Default)]
+pub struct Playback {
+    /// Audio file inside `assets/songs`
+    filename: String,
+    /// Audio that's waiting to be loaded and decoded
+    audio: Option<Handle<AudioSource>>,
+    song: Option<Arc<DecodedSong>>,
+    /// Seconds on the clock before the song starts
+    lead_in: f64,
+    /// Start playing as soon as the song is decoded
+    autoplay: bool,
+    loop_start: Option<f64>,
+    loop_end: Option<f64>,
+    /// Set when the player has to restart to match the clock
+    needs_sync: bool,
+}
+impl Playback {
+    /// Starts loading a song from `assets/songs`
+    pub fn load(
+        &mut self,
+        asset_server: &AssetServer,
+        filename: &str,
+        lead_in: f64,
+        autoplay: bool,
+    ) {
+        *self = Self {
+            filename: filename.to_string(),
+            audio: Some(asset_server.load(&*format!("songs/{}", filename))),
+            lead_in,
+            autoplay,
+            ..Default::default()
+        };
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    pub fn song(&self) -> Option<&Arc<DecodedSong>> {
+        self.song.as_ref()
+    }
+
+    /// Returns the time in the song, which is negative before it starts
+    pub fn song_time(&self, time: &ControlledTime) -> f64 {
+        time.seconds_since_startup() - self.lead_in
+    }
+
+    /// Moves the clock to a time in the song
+    pub fn seek(&mut self, time: &mut ControlledTime, song_time: f64) {
+        time.seek(song_time + self.lead_in);
+        self.needs_sync = true;
+    }
+
+    /// Returns the looped section, if both ends are set
+    pub fn loop_region(&self) -> Option<(f64, f64)> {
+        match (self.loop_start, self.loop_end) {
+            (Some(start), Some(end)) if start < end => Some((start, end)),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes the song once it's loaded, keeping the clock still until then
+fn decode_song(
+    asset_server: Res<AssetServer>,
+    audio_sources: Res<Assets<AudioSource>>,
+    mut time: ResMut<ControlledTime>,
+    mut playback: ResMut<Playback>,
+) {
+    let handle = match &playback.audio {
+        Some(handle) => handle.clone(),
+        None => return,
+    };
+
+    match asset_server.get_load_state(&handle) {
+        LoadState::Loaded => {
+            let filename = playback.filename.clone();
+            playback.song = audio_sources
+                .get(&handle)
+                .and_then(|source| DecodedSong::load_cached(&filename, source))
+                .map(Arc::new);
+        }
+        LoadState::Failed => {}
+        _ => {
+            time.pause();
+            return;
+        }
+    }
+
+    playback.audio = None;
+    playback.needs_sync = true;
+    // Decoding can take a while, which shouldn't count as song time
+    time.discard_elapsed();
+    if playback.autoplay {
+        time.resume();
+    }
+}
+
+/// Speeds that the song can be played at
+const PLAYBACK_SPEEDS: [f64; 3] = [0.5, 0.75, 1.];
+
+/// Pauses, seeks, changes speed and sets the loop with the keyboard
+fn playback_controls(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut time: ResMut<ControlledTime>,
+    mut playback: ResMut<Playback>,
+) {
+    if playback.audio.is_some() {
+        return;
+    }
+    let song_time = playback.song_time(&time);
+
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        if time.is_paused() {
+            time.resume();
+        } else {
+            time.pause();
+        }
+        playback.needs_sync = true;
+    }
+
+    let shift = keyboard_input.pressed(KeyCode::LShift) || keyboard_input.pressed(KeyCode::RShift);
+    let step = if shift { 5. } else { 1. };
+    if keyboard_input.just_pressed(KeyCode::Comma) {
+        playback.seek(&mut time, (song_time - step).max(0.));
+    }
+    if keyboard_input.just_pressed(KeyCode::Period) {
+        playback.seek(&mut time, song_time + step);
+    }
+    if keyboard_input.just_pressed(KeyCode::Home) {
+        playback.seek(&mut time, 0.);
+    }
+
+    let index = PLAYBACK_SPEEDS
+        .iter()
+        .position(|speed| *speed == time.speed())
+        .unwrap_or(PLAYBACK_SPEEDS.len() - 1);
+    if keyboard_input.just_pressed(KeyCode::Minus) && index > 0 {
+        time.set_speed(PLAYBACK_SPEEDS[index - 1]);
+        playback.needs_sync = true;
+    }
+    if keyboard_input.just_pressed(KeyCode::Equals) && index < PLAYBACK_SPEEDS.len() - 1 {
+        time.set_speed(PLAYBACK_SPEEDS[index + 1]);
+        playback.needs_sync = true;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::LBracket) {
+        playback.loop_start = Some(song_time.max(0.));
+    }
+    if keyboard_input.just_pressed(KeyCode::RBracket) {
+        playback.loop_end = Some(song_time.max(0.));
+    }
+    if keyboard_input.just_pressed(KeyCode::Backslash) {
+        playback.loop_start = None;
+        playback.loop_end = None;
+    }
+}
+
+/// Jumps back to the start of the loop, and restarts the player when the clock was changed
+fn sync_player(
+    mut player: NonSendMut<SongPlayer>,
+    mut time: ResMut<ControlledTime>,
+    mut playback: ResMut<Playback>,
+) {
+    if let Some((start, end)) = playback.loop_region() {
+        if playback.song_time(&time) >= end {
+            playback.seek(&mut time, start);
+        }
+    }
+
+    if !playback.needs_sync {
+        return;
+    }
+    playback.needs_sync = false;
+
+    match &playback.song {
+        Some(song) if !time.is_paused() => {
+            player.play(song, playback.song_time(&time), time.speed() as f32)
+        }
+        _ => player.stop(),
+    }
+}
+
+/// Keeps the song volume in sync with the settings
+fn apply_music_volume(settings: Res<Settings>, mut player: NonSendMut<SongPlayer>) {
+    if settings.is_changed() {
+        player.set_volume(settings.music());
+    }
+}
+
+fn stop_playback(mut player: NonSendMut<SongPlayer>, mut playback: ResMut<Playback>) {
+    player.stop();
+    *playback = Playback::default();
+}
+
+struct PlaybackText;
+fn setup_playback_text(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<RhythmConfig>,
+) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    bottom: Val::Px(10.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font_size: 20.0,
+                    font: asset_server.load(config.assets.font.as_str()),
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(PlaybackText);
+}
+
+fn update_playback_text(
+    time: Res<ControlledTime>,
+    playback: Res<Playback>,
+    mut query: Query<&mut Text, With<PlaybackText>>,
+) {
+    let status = if playback.audio.is_some() {
+        "Loading song...".to_string()
+    } else {
+        let loop_text = match playback.loop_region() {
+            Some((start, end)) => format!(" Loop: {:.2}-{:.2}.", start, end),
+            None => String::new(),
+        };
+        format!(
+            "Time: {:.2}. Speed: {:.0}%.{}{}",
+            playback.song_time(&time),
+            time.speed() * 100.,
+            loop_text,
+            if time.is_paused() { " Paused" } else { "" },
+        )
+    };
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "{}\nSpace: pause. ,/.: seek. Home: start. -/=: speed. [/]: loop, \\: clear loop",
+            status
+        );
+    }
+}
+
+fn despawn_playback_text(mut commands: Commands, query: Query<Entity, With<PlaybackText>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+pub struct PlaybackPlugin;
+impl Plugin for PlaybackPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Playback>()
+            .init_non_send_resource::<SongPlayer>()
+            .add_system(apply_music_volume.system());
+
+        for state in [AppState::MakeMap, AppState::EditMap].iter() {
+            app.add_system_set(
+                SystemSet::on_enter(state.clone()).with_system(setup_playback_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(state.clone())
+                    .with_system(decode_song.system().label("decode_song"))
+                    .with_system(
+                        playback_controls
+                            .system()
+                            .label("playback_controls")
+                            .after("decode_song"),
+                    )
+                    .with_system(sync_player.system().after("playback_controls"))
+                    .with_system(update_playback_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(state.clone())
+                    .with_system(stop_playback.system())
+                    .with_system(despawn_playback_text.system()),
+            );
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::consts::*;
+use crate::library;
+use crate::playback::OutputDevice;
+use crate::settings::Settings;
+use bevy::{prelude::*, tasks::AsyncComputeTaskPool};
+use rodio::{buffer::SamplesBuffer, OutputStreamHandle, Sink, Source};
+use std::{
+    io::Cursor,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    time::Duration,
+};
+
+/// Plays a looping part of a song while it's selected in the menu
+pub struct PreviewPlayer {
+    /// Handle of the audio device, if there is one
+    output: Option<OutputStreamHandle>,
+    /// Chart whose preview is playing
+    chart: Option<String>,
+    /// Preview being decoded in the background, which plays once it's done
+    loading: Option<Receiver<Option<SamplesBuffer<i16>>>>,
+    sink: Option<Sink>,
+    /// Previews that are fading out after being stopped
+    fading: Vec<Sink>,
+}
+impl FromWorld for PreviewPlayer {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            output: OutputDevice::handle(world),
+            chart: None,
+            loading: None,
+            sink: None,
+            fading: vec![],
+        }
+    }
+}
+impl PreviewPlayer {
+    /// Checks if the preview of a chart is the one playing
+    pub fn is_playing(&self, chart: &str) -> bool {
+        self.chart.as_deref() == Some(chart)
+    }
+
+    /// Starts decoding the preview of a chart's song, which plays once it's decoded
+    pub fn play(&mut self, pool: &AsyncComputeTaskPool, chart: &str, filename: &str, start: f64) {
+        self.stop();
+        // Don't try again every frame if the song can't be played
+        self.chart = Some(chart.to_string());
+        if self.output.is_none() {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let filename = filename.to_string();
+        pool.spawn(async move {
+            // Nobody is listening if the preview was stopped while decoding
+            let _ = sender.send(preview_source(&filename, start));
+        })
+        .detach();
+        self.loading = Some(receiver);
+    }
+
+    /// Plays the preview being decoded if it's done
+    fn play_decoded(&mut self, volume: f32) {
+        let source = match self.loading.as_ref().map(Receiver::try_recv) {
+            Some(Ok(source)) => source,
+            Some(Err(TryRecvError::Disconnected)) => None,
+            Some(Err(TryRecvError::Empty)) | None => return,
+        };
+        self.loading = None;
+
+        let (handle, source) = match (&self.output, source) {
+            (Some(handle), Some(source)) => (handle, source),
+            _ => return,
+        };
+        if let Ok(sink) = Sink::try_new(handle) {
+            sink.set_volume(volume);
+            sink.append(source.repeat_infinite());
+            self.sink = Some(sink);
+        }
+    }
+
+    /// Fades out the current preview
+    pub fn stop(&mut self) {
+        self.chart = None;
+        self.loading = None;
+        if let Some(sink) = self.sink.take() {
+            self.fading.push(sink);
+        }
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        if let Some(sink) = &self.sink {
+            sink.set_volume(volume);
+        }
+    }
+
+    /// Lowers the volume of the previews that are fading out, and stops them once they're silent
+    pub fn fade(&mut self, delta_seconds: f32, volume: f32) {
+        let step = delta_seconds / PREVIEW_STOP_FADE * volume;
+        self.fading.retain(|sink| {
+            let new_volume = sink.volume() - step;
+            sink.set_volume(new_volume.max(0.));
+            if new_volume <= 0. {
+                sink.stop();
+            }
+            new_volume > 0.
+        });
+    }
+}
+
+/// Decodes the part of a song used for its preview, fading in and out so it loops smoothly
+fn preview_source(filename: &str, start: f64) -> Option<SamplesBuffer<i16>> {
+    let bytes = library::read_file(filename).ok()?;
+    let decoder = rodio::Decoder::new(Cursor::new(bytes)).ok()?;
+    let channels = decoder.channels();
+    let sample_rate = decoder.sample_rate();
+
+    let mut samples: Vec<i16> = decoder
+        .skip_duration(Duration::from_secs_f64(start.max(0.)))
+        .take_duration(Duration::from_secs_f64(PREVIEW_LENGTH))
+        .collect();
+    if samples.is_empty() {
+        return None;
+    }
+
+    let frames = samples.len() / channels as usize;
+    let fade_frames = (PREVIEW_LOOP_FADE * sample_rate as f64) as usize;
+    for (i, frame) in samples.chunks_mut(channels as usize).enumerate() {
+        let gain = (i.min(frames - i) as f32 / fade_frames as f32).min(1.);
+        for sample in frame {
+            *sample = (*sample as f32 * gain) as i16;
+        }
+    }
+
+    Some(SamplesBuffer::new(channels, sample_rate, samples))
+}
+
+/// Plays decoded previews, keeps the preview volume in sync with the settings, and fades out stopped previews
+fn update_preview_player(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut player: NonSendMut<PreviewPlayer>,
+) {
+    if settings.is_changed() {
+        player.set_volume(settings.music());
+    }
+    player.play_decoded(settings.music());
+    player.fade(time.delta_seconds(), settings.music());
+}
+
+/// Stops the preview, and the one being decoded, when leaving the song select
+pub fn stop_preview(mut player: NonSendMut<PreviewPlayer>) {
+    player.stop();
+}
+
+pub struct PreviewPlugin;
+impl Plugin for PreviewPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_non_send_resource::<PreviewPlayer>()
+            .add_system(update_preview_player.system());
+    }
+}
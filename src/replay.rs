@@ -0,0 +1,164 @@
+use crate::arrows::{LaneInput, PlayMode};
+use crate::consts::*;
+use crate::settings::{data_folder, Settings};
+use crate::time::ControlledTime;
+use crate::types::load_config;
+use bevy::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The lane presses of one play, saved so it can be watched again
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Replay {
+    /// Path of the chart in `assets/songs`, without its extension
+    pub chart: String,
+    /// Hash of the chart's notes when it was played
+    pub chart_hash: String,
+    /// Score of the play, to check that watching it scores the same
+    pub score: usize,
+    /// Seconds since the Unix epoch
+    pub played_at: u64,
+    // Tables have to come after the values to be written as toml
+    /// Settings the chart was played with
+    pub settings: Settings,
+    pub inputs: Vec<LaneInput>,
+}
+impl Replay {
+    pub fn save(&self) {
+        let path = replay_path(&self.chart, self.played_at);
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder).expect("Couldn't create replays folder");
+        }
+        let text = toml::to_string(self).expect("Couldn't convert to toml text");
+        fs::write(path, text).expect("Couldn't write replay file");
+    }
+
+    /// Reads a replay file, which can be anywhere
+    pub fn load(path: &Path) -> Option<Self> {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|text| toml::from_str(&text).ok())
+    }
+
+    /// Returns the last saved replay of this version of a chart
+    pub fn latest(chart: &str, chart_hash: &str) -> Option<Self> {
+        let prefix = file_stem(chart);
+        fs::read_dir(replays_folder())
+            .ok()?
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+            .filter_map(|entry| Replay::load(&entry.path()))
+            .filter(|replay| replay.chart == chart && replay.chart_hash == chart_hash)
+            .max_by_key(|replay| replay.played_at)
+    }
+}
+
+fn replays_folder() -> PathBuf {
+    data_folder().join("replays")
+}
+
+/// Charts can be in folders, so the `/` are replaced to keep every replay in one folder
+fn file_stem(chart: &str) -> String {
+    chart.replace('/', "_")
+}
+
+fn replay_path(chart: &str, played_at: u64) -> PathBuf {
+    replays_folder().join(format!("{}_{}.toml", file_stem(chart), played_at))
+}
+
+/// Replay being recorded while playing, or being watched
+#[derive(Default)]
+pub struct CurrentReplay {
+    pub replay: Replay,
+    /// Index of the next input to send while watching
+    next: usize,
+}
+
+impl CurrentReplay {
+    /// Starts with a replay to be watched
+    pub fn watching(replay: Replay) -> Self {
+        Self { replay, next: 0 }
+    }
+}
+
+/// Event to start watching a replay
+pub struct WatchReplay(pub Replay);
+
+fn start_recording(mode: Res<PlayMode>, mut current: ResMut<CurrentReplay>) {
+    if *mode != PlayMode::Watch {
+        current.replay = Replay::default();
+    }
+    current.next = 0;
+}
+
+/// Keeps the lane presses of the song being played
+fn record_inputs(
+    mode: Res<PlayMode>,
+    mut lane_inputs: EventReader<LaneInput>,
+    mut current: ResMut<CurrentReplay>,
+) {
+    if *mode == PlayMode::Watch {
+        return;
+    }
+    current.replay.inputs.extend(lane_inputs.iter().copied());
+}
+
+/// Sends the lane presses of the replay being watched once the song reaches them
+fn send_replay_inputs(
+    time: Res<ControlledTime>,
+    mode: Res<PlayMode>,
+    mut current: ResMut<CurrentReplay>,
+    mut lane_inputs: EventWriter<LaneInput>,
+) {
+    if *mode != PlayMode::Watch {
+        return;
+    }
+
+    let secs = time.seconds_since_startup() - SONG_START_DELAY;
+    while let Some(input) = current.replay.inputs.get(current.next).copied() {
+        if input.time > secs {
+            break;
+        }
+        lane_inputs.send(input);
+        current.next += 1;
+    }
+}
+
+fn start_watching(
+    mut commands: Commands,
+    mut events: EventReader<WatchReplay>,
+    mut mode: ResMut<PlayMode>,
+    mut current: ResMut<CurrentReplay>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if let Some(WatchReplay(replay)) = events.iter().last() {
+        let config = load_config(&format!("{}.toml", replay.chart));
+        commands.insert_resource(config);
+        *mode = PlayMode::Watch;
+        current.replay = replay.clone();
+        state
+            .set(AppState::Game)
+            .expect("Couldn't switch state to Game");
+    }
+}
+
+pub struct ReplayPlugin;
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<CurrentReplay>()
+            .add_event::<WatchReplay>()
+            .add_system(start_watching.system())
+            .add_system_set(
+                SystemSet::on_enter(AppState::Game).with_system(start_recording.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(send_replay_inputs.system().label("lane_input"))
+                    .with_system(record_inputs.system().after("lane_input")),
+            );
+    }
+}
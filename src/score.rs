@@ -1,4 +1,21 @@
-use crate::consts::*;
+/// How well a note was hit
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Judgement {
+    Perfect,
+    Great,
+    Good,
+    Miss,
+}
+impl Judgement {
+    /// Returns the judgement for the points given by a hit
+    pub fn from_points(points: usize) -> Self {
+        match points {
+            90..=usize::MAX => Judgement::Perfect,
+            50..=89 => Judgement::Great,
+            _ => Judgement::Good,
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct ScoreResource {
@@ -6,25 +23,41 @@ pub struct ScoreResource {
     fails: usize,
 
     score: usize,
+    /// Notes hit in a row since the last miss
+    combo: usize,
+    max_combo: usize,
+    perfects: usize,
+    greats: usize,
+    goods: usize,
 }
 
 impl ScoreResource {
-    /// Increases number of corrects and adds to score
-    pub fn increase_correct(&mut self, distance: f32) -> usize {
+    /// Increases number of corrects and adds to score.
+    /// `offset` is how early or late the press was, from -1 to 1 being the edges of the clicking threshold
+    pub fn increase_correct(&mut self, offset: f32) -> usize {
         self.corrects += 1;
+        self.combo += 1;
 
         // Get a value from 0 to 1 according to how close the press was
-        let score_multiplier = (THRESHOLD - distance.abs()) / THRESHOLD;
+        let score_multiplier = 1. - offset.abs();
         // Give at least 10 points and 100 at max
         let points = (score_multiplier * 100.).min(100.).max(10.) as usize;
         self.score += points;
 
+        self.max_combo = self.max_combo.max(self.combo);
+        match Judgement::from_points(points) {
+            Judgement::Perfect => self.perfects += 1,
+            Judgement::Great => self.greats += 1,
+            _ => self.goods += 1,
+        }
+
         points
     }
 
     /// Increases number of failures
     pub fn increase_fails(&mut self) {
         self.fails += 1;
+        self.combo = 0;
     }
 
     // Getters
@@ -38,4 +71,29 @@ impl ScoreResource {
     pub fn fails(&self) -> usize {
         self.fails
     }
+    pub fn combo(&self) -> usize {
+        self.combo
+    }
+    pub fn max_combo(&self) -> usize {
+        self.max_combo
+    }
+
+    /// Returns how many notes got a judgement
+    pub fn count(&self, judgement: Judgement) -> usize {
+        match judgement {
+            Judgement::Perfect => self.perfects,
+            Judgement::Great => self.greats,
+            Judgement::Good => self.goods,
+            Judgement::Miss => self.fails,
+        }
+    }
+
+    /// Returns the points scored out of the most that could be scored, from 0 to 1
+    pub fn accuracy(&self) -> f64 {
+        let notes = self.corrects + self.fails;
+        if notes == 0 {
+            return 0.;
+        }
+        self.score as f64 / (notes * 100) as f64
+    }
 }
@@ -0,0 +1,171 @@
+use crate::consts::*;
+use bevy::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    env, fs, io,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Volumes that can be changed in the settings menu
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Volume {
+    Master,
+    Music,
+    Sfx,
+}
+impl Volume {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Volume::Master => "Master",
+            Volume::Music => "Music",
+            Volume::Sfx => "Effects",
+        }
+    }
+}
+
+/// User settings, saved between runs
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.,
+            music_volume: 0.8,
+            sfx_volume: 0.8,
+        }
+    }
+}
+impl Settings {
+    /// Reads the settings file, or returns the default settings if there isn't one.
+    /// A file that can't be read is kept as a backup instead of being overwritten, and if it can't
+    /// be moved out of the way either, it's returned as read only
+    fn load() -> (Self, bool) {
+        let path = settings_path();
+        let loaded = match fs::read_to_string(&path) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return (Self::default(), false)
+            }
+            Err(error) => Err(error.to_string()),
+            Ok(text) => toml::from_str(&text).map_err(|error| error.to_string()),
+        };
+        let error = match loaded {
+            Ok(settings) => return (settings, false),
+            Err(error) => error,
+        };
+
+        let backup = path.with_extension(format!("{}.toml", now()));
+        match fs::rename(&path, &backup) {
+            Ok(()) => {
+                warn!(
+                    "Couldn't read {}, using the default settings. It was moved to {}: {}",
+                    path.display(),
+                    backup.display(),
+                    error
+                );
+                (Self::default(), false)
+            }
+            Err(_) => {
+                warn!(
+                    "Couldn't read {}, so settings won't be saved: {}",
+                    path.display(),
+                    error
+                );
+                (Self::default(), true)
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let path = settings_path();
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder).expect("Couldn't create settings folder");
+        }
+        let text = toml::to_string(self).expect("Couldn't convert to toml text");
+        fs::write(path, text).expect("Couldn't write settings file");
+    }
+
+    pub fn volume(&self, volume: Volume) -> f32 {
+        match volume {
+            Volume::Master => self.master_volume,
+            Volume::Music => self.music_volume,
+            Volume::Sfx => self.sfx_volume,
+        }
+    }
+
+    /// Adds `change` to a volume, keeping it between 0 and 1
+    pub fn change_volume(&mut self, volume: Volume, change: f32) {
+        let value = match volume {
+            Volume::Master => &mut self.master_volume,
+            Volume::Music => &mut self.music_volume,
+            Volume::Sfx => &mut self.sfx_volume,
+        };
+        // Round so steps of 0.1 don't drift
+        *value = ((*value + change).clamp(0., 1.) * 100.).round() / 100.;
+    }
+
+    /// Volume songs are played at
+    pub fn music(&self) -> f32 {
+        self.master_volume * self.music_volume
+    }
+
+    /// Volume sound effects are played at, before their category's volume
+    pub fn sfx(&self) -> f32 {
+        self.master_volume * self.sfx_volume
+    }
+}
+
+/// Returns the path of the settings file in the user's config folder
+fn settings_path() -> PathBuf {
+    user_folder("XDG_CONFIG_HOME", ".config").join("settings.toml")
+}
+
+/// Returns the game's folder for files that aren't settings, like the high scores
+pub fn data_folder() -> PathBuf {
+    user_folder("XDG_DATA_HOME", ".local/share")
+}
+
+/// Returns the seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Returns the game's folder inside a user folder, from `xdg_var`, `%APPDATA%` or `home_folder` in `$HOME`
+fn user_folder(xdg_var: &str, home_folder: &str) -> PathBuf {
+    let folder = env::var_os(xdg_var)
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(home_folder)))
+        .unwrap_or_else(|| PathBuf::from("."));
+    folder.join("bevy_rhythm")
+}
+
+/// Set when a settings file that couldn't be read couldn't be moved out of the way either,
+/// so it isn't overwritten by the settings of this session
+struct ReadOnlySettings(bool);
+
+fn save_settings(settings: Res<Settings>, read_only: Res<ReadOnlySettings>) {
+    if !read_only.0 {
+        settings.save();
+    }
+}
+
+pub struct SettingsPlugin;
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let (settings, read_only) = Settings::load();
+        app.insert_resource(settings)
+            .insert_resource(ReadOnlySettings(read_only))
+            .add_system_set(
+                SystemSet::on_exit(AppState::Settings).with_system(save_settings.system()),
+            );
+    }
+}
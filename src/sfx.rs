@@ -0,0 +1,265 @@
+use crate::arrows::JudgementEvent;
+use crate::config::RhythmConfig;
+use crate::consts::*;
+use crate::playback::OutputDevice;
+use crate::score::Judgement;
+use crate::settings::Settings;
+use bevy::{prelude::*, utils::HashMap};
+use rodio::{OutputStreamHandle, Source};
+use std::{f32::consts::PI, io::Cursor, path::Path};
+
+/// Groups of sound effects that share a volume
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SfxCategory {
+    Hit,
+    Miss,
+    Menu,
+    Combo,
+    Metronome,
+}
+
+/// Sound effects that can be played with a [`PlaySfx`] event
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Sfx {
+    HitPerfect,
+    HitGreat,
+    HitGood,
+    Miss,
+    MenuMove,
+    MenuConfirm,
+    ComboMilestone,
+    Beat,
+    Downbeat,
+    AssistTick,
+}
+impl Sfx {
+    const ALL: [Sfx; 10] = [
+        Sfx::HitPerfect,
+        Sfx::HitGreat,
+        Sfx::HitGood,
+        Sfx::Miss,
+        Sfx::MenuMove,
+        Sfx::MenuConfirm,
+        Sfx::ComboMilestone,
+        Sfx::Beat,
+        Sfx::Downbeat,
+        Sfx::AssistTick,
+    ];
+
+    /// Name of the file in `assets/sounds` that replaces the built in sound
+    fn file_stem(&self) -> &'static str {
+        match self {
+            Sfx::HitPerfect => "hit_perfect",
+            Sfx::HitGreat => "hit_great",
+            Sfx::HitGood => "hit_good",
+            Sfx::Miss => "miss",
+            Sfx::MenuMove => "menu_move",
+            Sfx::MenuConfirm => "menu_confirm",
+            Sfx::ComboMilestone => "combo_milestone",
+            Sfx::Beat => "metronome_beat",
+            Sfx::Downbeat => "metronome_downbeat",
+            Sfx::AssistTick => "assist_tick",
+        }
+    }
+
+    pub fn category(&self) -> SfxCategory {
+        match self {
+            Sfx::HitPerfect | Sfx::HitGreat | Sfx::HitGood => SfxCategory::Hit,
+            Sfx::Miss => SfxCategory::Miss,
+            Sfx::MenuMove | Sfx::MenuConfirm => SfxCategory::Menu,
+            Sfx::ComboMilestone => SfxCategory::Combo,
+            Sfx::Beat | Sfx::Downbeat | Sfx::AssistTick => SfxCategory::Metronome,
+        }
+    }
+
+    /// Sound used when there's no file for it in `assets/sounds`
+    fn built_in(&self) -> AudioSource {
+        match self {
+            Sfx::HitPerfect => synth(&[1760.], 0.06, 0.5),
+            Sfx::HitGreat => synth(&[1320.], 0.06, 0.45),
+            Sfx::HitGood => synth(&[880.], 0.06, 0.4),
+            Sfx::Miss => synth(&[110., 117.], 0.15, 0.5),
+            Sfx::MenuMove => synth(&[660.], 0.03, 0.3),
+            Sfx::MenuConfirm => synth(&[880., 1320.], 0.1, 0.35),
+            Sfx::ComboMilestone => synth(&[1047., 1319., 1568.], 0.3, 0.35),
+            Sfx::Beat => synth(&[1000.], 0.05, 0.5),
+            Sfx::Downbeat => synth(&[1500.], 0.05, 0.6),
+            Sfx::AssistTick => synth(&[2500.], 0.05, 0.35),
+        }
+    }
+}
+
+/// Makes a WAV file with decaying sine waves at each of `frequencies`
+fn synth(frequencies: &[f32], duration: f32, volume: f32) -> AudioSource {
+    const SAMPLE_RATE: u32 = 44100;
+    let len = (SAMPLE_RATE as f32 * duration) as u32;
+    let samples: Vec<i16> = (0..len)
+        .map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let wave = frequencies
+                .iter()
+                .map(|frequency| (2. * PI * frequency * t).sin())
+                .sum::<f32>()
+                / frequencies.len() as f32;
+            let value = wave * (-t * 4. / duration).exp() * volume;
+            (value * i16::MAX as f32) as i16
+        })
+        .collect();
+
+    let data_len = samples.len() as u32 * 2;
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, mono
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    bytes.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    AudioSource {
+        bytes: bytes.into(),
+    }
+}
+
+/// Volume of each category of sound effects, from 0 to 1
+pub struct SfxVolumes {
+    pub hit: f32,
+    pub miss: f32,
+    pub menu: f32,
+    pub combo: f32,
+    pub metronome: f32,
+}
+impl Default for SfxVolumes {
+    fn default() -> Self {
+        Self {
+            hit: 0.8,
+            miss: 0.8,
+            menu: 0.6,
+            combo: 0.8,
+            metronome: 0.8,
+        }
+    }
+}
+impl SfxVolumes {
+    pub fn get(&self, category: SfxCategory) -> f32 {
+        match category {
+            SfxCategory::Hit => self.hit,
+            SfxCategory::Miss => self.miss,
+            SfxCategory::Menu => self.menu,
+            SfxCategory::Combo => self.combo,
+            SfxCategory::Metronome => self.metronome,
+        }
+    }
+}
+
+/// Keeps the sound of each [`Sfx`]
+struct SfxSounds {
+    handles: HashMap<Sfx, Handle<AudioSource>>,
+}
+impl FromWorld for SfxSounds {
+    fn from_world(world: &mut World) -> Self {
+        let world = world.cell();
+
+        let asset_server = world.get_resource::<AssetServer>().unwrap();
+        let folder = &world.get_resource::<RhythmConfig>().unwrap().assets.sounds;
+        let mut sounds = world.get_resource_mut::<Assets<AudioSource>>().unwrap();
+
+        let mut handles = HashMap::default();
+        for sfx in Sfx::ALL.iter() {
+            // Use a file from `assets/sounds` if there's one, so sounds can be replaced
+            let file = AUDIO_EXTENSIONS
+                .iter()
+                .map(|extension| format!("{}/{}.{}", folder, sfx.file_stem(), extension))
+                .find(|file| Path::new("assets").join(file).exists());
+            let handle = match file {
+                Some(file) => asset_server.load(file.as_str()),
+                None => sounds.add(sfx.built_in()),
+            };
+            handles.insert(*sfx, handle);
+        }
+        SfxSounds { handles }
+    }
+}
+
+/// Plays sound effects on the audio device, unlike bevy's `Audio` it can change their volume
+struct SfxPlayer {
+    /// Handle of the audio device, if there is one
+    output: Option<OutputStreamHandle>,
+}
+impl FromWorld for SfxPlayer {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            output: OutputDevice::handle(world),
+        }
+    }
+}
+
+/// Event to play a sound effect
+pub struct PlaySfx(pub Sfx);
+
+fn play_sfx(
+    mut events: EventReader<PlaySfx>,
+    player: NonSend<SfxPlayer>,
+    sounds: Res<SfxSounds>,
+    sources: Res<Assets<AudioSource>>,
+    volumes: Res<SfxVolumes>,
+    settings: Res<Settings>,
+) {
+    for PlaySfx(sfx) in events.iter() {
+        let handle = match &player.output {
+            Some(handle) => handle,
+            None => return,
+        };
+        // Sounds from files might still be loading
+        let source = match sounds.handles.get(sfx).and_then(|h| sources.get(h)) {
+            Some(source) => source,
+            None => continue,
+        };
+
+        if let Ok(decoder) = rodio::Decoder::new(Cursor::new(source.clone())) {
+            let volume = settings.sfx() * volumes.get(sfx.category());
+            // Failing to play a sound effect isn't worth stopping the game for
+            let _ = handle.play_raw(decoder.convert_samples().amplify(volume));
+        }
+    }
+}
+
+/// Plays the sound for each hit or miss, and for reaching a combo milestone
+fn judgement_sounds(mut events: EventReader<JudgementEvent>, mut sfx: EventWriter<PlaySfx>) {
+    for event in events.iter() {
+        let sound = match event.judgement {
+            Judgement::Perfect => Sfx::HitPerfect,
+            Judgement::Great => Sfx::HitGreat,
+            Judgement::Good => Sfx::HitGood,
+            Judgement::Miss => Sfx::Miss,
+        };
+        sfx.send(PlaySfx(sound));
+
+        if event.combo > 0 && event.combo % COMBO_MILESTONE == 0 {
+            sfx.send(PlaySfx(Sfx::ComboMilestone));
+        }
+    }
+}
+
+pub struct SfxPlugin;
+impl Plugin for SfxPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SfxSounds>()
+            .init_resource::<SfxVolumes>()
+            .init_non_send_resource::<SfxPlayer>()
+            .add_event::<PlaySfx>()
+            .add_system(play_sfx.system())
+            .add_system_set(
+                SystemSet::on_update(AppState::Game).with_system(judgement_sounds.system()),
+            );
+    }
+}
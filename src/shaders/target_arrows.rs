@@ -1,6 +1,6 @@
 use super::*;
 use crate::arrows::CorrectArrowEvent;
-use crate::consts::*;
+use crate::config::RhythmConfig;
 use crate::types::Directions::{self, *};
 
 pub fn setup_target_arrows(
@@ -9,6 +9,7 @@ pub fn setup_target_arrows(
     mut shaders: ResMut<Assets<Shader>>,
     mut render_graph: ResMut<RenderGraph>,
     window: Res<WindowDescriptor>,
+    config: Res<RhythmConfig>,
 ) {
     // Create a new shader pipeline
     let pipeline_handle = pipelines.add(PipelineDescriptor::default_config(ShaderStages {
@@ -40,8 +41,12 @@ pub fn setup_target_arrows(
             Right => 0.6,
         };
 
-        let mut transform =
-            Transform::from_translation(Vec3::new(TARGET_POSITION, direction.y(), z));
+        let layout = &config.layout;
+        let mut transform = Transform::from_translation(Vec3::new(
+            layout.target_position,
+            layout.lane_y(*direction),
+            z,
+        ));
         transform.scale = Vec3::new(300., 300., 1.);
         commands
             .spawn_bundle(SpriteBundle {
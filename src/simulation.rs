@@ -0,0 +1,380 @@
+use crate::arrows::{LaneInput, PlayMode};
+use crate::cli::{exit_with_error, exit_with_usage};
+use crate::config::{GameStates, RhythmConfig};
+use crate::consts::*;
+use crate::replay::{CurrentReplay, Replay};
+use crate::score::{Judgement, ScoreResource};
+use crate::time::ControlledTime;
+use crate::types::{load_config, Directions, SongConfig};
+use crate::SimulationPlugins;
+use bevy::prelude::*;
+use std::path::Path;
+
+/// Lane presses and releases queued with `Simulation::press` and `release`, sorted by time
+#[derive(Default)]
+struct QueuedInputs {
+    inputs: Vec<LaneInput>,
+    /// Index of the next input to send
+    next: usize,
+}
+
+/// Sends the queued inputs once the song reaches them
+fn send_queued_inputs(
+    time: Res<ControlledTime>,
+    mut queued: ResMut<QueuedInputs>,
+    mut lane_inputs: EventWriter<LaneInput>,
+) {
+    let secs = time.seconds_since_startup() - SONG_START_DELAY;
+    while let Some(input) = queued.inputs.get(queued.next).copied() {
+        if input.time > secs {
+            break;
+        }
+        lane_inputs.send(input);
+        queued.next += 1;
+    }
+}
+
+/// A song played without a window or GPU, with time stepped by hand.
+/// Only the plugins with the rules of the game are added, and the input comes from autoplay,
+/// a replay or inputs queued with `press` and `release`
+pub struct Simulation {
+    app: App,
+}
+impl Simulation {
+    pub fn new(config: SongConfig, mode: PlayMode, replay: Option<Replay>) -> Self {
+        let mut builder = App::build();
+        builder
+            .add_plugins(MinimalPlugins)
+            .insert_resource(RhythmConfig {
+                states: GameStates {
+                    initial: AppState::Game,
+                    after_song: AppState::Results,
+                },
+                ..Default::default()
+            })
+            .init_resource::<ControlledTime>()
+            // Nothing presses keys without a window, but the keyboard input is still read
+            .init_resource::<Input<KeyCode>>()
+            .insert_resource(config)
+            .insert_resource(mode)
+            .insert_resource(CurrentReplay::watching(replay.unwrap_or_default()))
+            .init_resource::<QueuedInputs>()
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(send_queued_inputs.system().label("lane_input")),
+            )
+            .add_plugins(SimulationPlugins);
+        Self { app: builder.app }
+    }
+
+    /// Advances time by `seconds` and runs one frame
+    pub fn step(&mut self, seconds: f64) {
+        self.app
+            .world
+            .get_resource_mut::<ControlledTime>()
+            .expect("Couldn't find ControlledTime")
+            .advance(seconds);
+        self.app.update();
+    }
+
+    /// Queues a press of a lane at a time of the song, sent once the simulation reaches it
+    pub fn press(&mut self, direction: Directions, time: f64) {
+        self.queue(LaneInput {
+            time,
+            direction,
+            pressed: true,
+        });
+    }
+
+    /// Queues a release of a lane at a time of the song, which only matters for hold notes
+    pub fn release(&mut self, direction: Directions, time: f64) {
+        self.queue(LaneInput {
+            time,
+            direction,
+            pressed: false,
+        });
+    }
+
+    fn queue(&mut self, input: LaneInput) {
+        let mut queued = self
+            .app
+            .world
+            .get_resource_mut::<QueuedInputs>()
+            .expect("Couldn't find QueuedInputs");
+        // Inputs queued for a time that already passed are sent on the next step
+        let index = queued.next.max(
+            queued
+                .inputs
+                .partition_point(|other| other.time <= input.time),
+        );
+        queued.inputs.insert(index, input);
+    }
+
+    /// Steps the simulation until the song is over, or a while after it should have been
+    pub fn finish(&mut self) {
+        let end_time = self
+            .app
+            .world
+            .get_resource::<SongConfig>()
+            .map(|config| config.end_time)
+            .unwrap_or(0.);
+        let frames =
+            ((end_time + SONG_START_DELAY + SONG_END_DELAY) / SIMULATION_STEP) as usize + 60;
+        for _ in 0..frames {
+            if self.is_finished() {
+                break;
+            }
+            self.step(SIMULATION_STEP);
+        }
+    }
+
+    /// Checks if the song is over and the game went to the results
+    pub fn is_finished(&self) -> bool {
+        let state = self.app.world.get_resource::<State<AppState>>();
+        state.map(|state| state.current() == &AppState::Results) == Some(true)
+    }
+
+    /// Returns the replay being watched, or the one recorded from the presses
+    pub fn replay(&self) -> &Replay {
+        &self
+            .app
+            .world
+            .get_resource::<CurrentReplay>()
+            .expect("Couldn't find CurrentReplay")
+            .replay
+    }
+
+    pub fn score(&self) -> &ScoreResource {
+        self.app
+            .world
+            .get_resource::<ScoreResource>()
+            .expect("Couldn't find ScoreResource")
+    }
+}
+
+const USAGE: &str = "Usage: bevy_rhythm simulate <chart> [--replay <replay file>]
+
+Plays a chart in `assets/songs` without opening a window, with autoplay or the inputs of a replay,
+and prints the score. Exits with an error if a replay doesn't score the same as when it was recorded.";
+
+/// Simulates a chart from the command line
+pub fn run_cli(args: &[String]) {
+    let mut args = args.iter();
+    let chart = match args.next() {
+        Some(chart) if !chart.starts_with("--") => chart.trim_end_matches(".toml").to_string(),
+        _ => exit_with_usage("Missing chart", USAGE),
+    };
+
+    let mut replay = None;
+    while let Some(flag) = args.next() {
+        let value = match args.next() {
+            Some(value) => value,
+            None => exit_with_usage(&format!("Missing value for {}", flag), USAGE),
+        };
+        match flag.as_str() {
+            "--replay" => match Replay::load(Path::new(value)) {
+                Some(loaded) => replay = Some(loaded),
+                None => exit_with_usage(&format!("Couldn't read replay {}", value), USAGE),
+            },
+            _ => exit_with_usage(&format!("Unknown option {}", flag), USAGE),
+        }
+    }
+
+    let config = load_config(&format!("{}.toml", chart));
+    if let Some(replay) = &replay {
+        if replay.chart_hash != config.chart_hash {
+            eprintln!("The replay was recorded on a different version of the chart");
+        }
+    }
+    let mode = match replay {
+        Some(_) => PlayMode::Watch,
+        None => PlayMode::Autoplay,
+    };
+    let recorded_score = replay.as_ref().map(|replay| replay.score);
+    let mut simulation = Simulation::new(config, mode, replay);
+    simulation.finish();
+
+    let score = simulation.score();
+    println!(
+        "Score: {}. Accuracy: {:.2}%. Max combo: {}\nPerfect: {}. Great: {}. Good: {}. Miss: {}",
+        score.score(),
+        score.accuracy() * 100.,
+        score.max_combo(),
+        score.count(Judgement::Perfect),
+        score.count(Judgement::Great),
+        score.count(Judgement::Good),
+        score.count(Judgement::Miss),
+    );
+
+    if !simulation.is_finished() {
+        exit_with_error("The song didn't finish");
+    }
+    if let Some(recorded_score) = recorded_score {
+        if recorded_score != score.score() {
+            exit_with_error(&format!(
+                "The replay was recorded with a score of {}",
+                recorded_score
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Layout;
+    use crate::types::test_charts::{chart, note};
+    use crate::types::Speed;
+
+    /// A chart with a note on each lane, one second apart
+    fn song() -> SongConfig {
+        let arrows = Directions::ALL
+            .iter()
+            .enumerate()
+            .map(|(i, direction)| note(1. + i as f64, *direction, None))
+            .collect();
+        chart(arrows)
+    }
+
+    /// A chart with a hold note from one second to two, and a tap on the same lane after it
+    fn hold_song() -> SongConfig {
+        chart(vec![
+            note(1., Directions::Up, Some(2.)),
+            note(3., Directions::Up, None),
+        ])
+    }
+
+    fn play(mode: PlayMode, presses: &[(Directions, f64)]) -> Simulation {
+        let mut simulation = Simulation::new(song(), mode, None);
+        for (direction, time) in presses {
+            simulation.press(*direction, *time);
+        }
+        simulation.finish();
+        assert!(simulation.is_finished());
+        simulation
+    }
+
+    #[test]
+    fn autoplay_scores_all_perfect() {
+        let simulation = play(PlayMode::Autoplay, &[]);
+        let score = simulation.score();
+        assert_eq!(score.count(Judgement::Perfect), 4);
+        assert_eq!(score.count(Judgement::Miss), 0);
+        assert_eq!(score.max_combo(), 4);
+        assert_eq!(score.score(), 400);
+    }
+
+    #[test]
+    fn pressing_nothing_misses_everything() {
+        let simulation = play(PlayMode::Play, &[]);
+        let score = simulation.score();
+        assert_eq!(score.count(Judgement::Miss), 4);
+        assert_eq!(score.corrects(), 0);
+        assert_eq!(score.combo(), 0);
+        assert_eq!(score.max_combo(), 0);
+        assert_eq!(score.score(), 0);
+    }
+
+    #[test]
+    fn presses_outside_the_threshold_miss() {
+        let layout = Layout::default();
+        let hit_window = (layout.threshold / layout.speed(Speed::Medium)) as f64;
+        let presses: Vec<(Directions, f64)> = Directions::ALL
+            .iter()
+            .enumerate()
+            .map(|(i, direction)| (*direction, 1. + i as f64))
+            .collect();
+
+        // The first note is pressed just too late, the rest right on time
+        let mut late = presses.clone();
+        late[0].1 += hit_window * 1.01;
+        let simulation = play(PlayMode::Play, &late);
+        let score = simulation.score();
+        assert_eq!(score.count(Judgement::Miss), 1);
+        assert_eq!(score.count(Judgement::Perfect), 3);
+
+        // Just inside it still hits
+        let mut barely = presses;
+        barely[0].1 += hit_window * 0.99;
+        let simulation = play(PlayMode::Play, &barely);
+        let score = simulation.score();
+        assert_eq!(score.count(Judgement::Miss), 0);
+        assert_eq!(score.count(Judgement::Good), 1);
+    }
+
+    #[test]
+    fn replays_score_the_same() {
+        let layout = Layout::default();
+        let hit_window = (layout.threshold / layout.speed(Speed::Medium)) as f64;
+        // Early and late presses, one of them outside the threshold
+        let jitter = [-0.3, 0.55, 1.2, -0.85];
+        let presses: Vec<(Directions, f64)> = Directions::ALL
+            .iter()
+            .zip(jitter.iter())
+            .enumerate()
+            .map(|(i, (direction, jitter))| (*direction, 1. + i as f64 + jitter * hit_window))
+            .collect();
+        let played = play(PlayMode::Play, &presses);
+        let replay = played.replay().clone();
+        assert_eq!(replay.inputs.len(), presses.len());
+
+        let mut watched = Simulation::new(song(), PlayMode::Watch, Some(replay));
+        watched.finish();
+        assert!(watched.is_finished());
+        let (played, watched) = (played.score(), watched.score());
+        assert_eq!(played.score(), watched.score());
+        assert_eq!(played.max_combo(), watched.max_combo());
+        for judgement in [
+            Judgement::Perfect,
+            Judgement::Great,
+            Judgement::Good,
+            Judgement::Miss,
+        ]
+        .iter()
+        {
+            assert_eq!(played.count(*judgement), watched.count(*judgement));
+        }
+        assert_eq!(played.count(Judgement::Miss), 1);
+    }
+
+    fn play_holds(inputs: &[(bool, f64)]) -> Simulation {
+        let mut simulation = Simulation::new(hold_song(), PlayMode::Play, None);
+        for (pressed, time) in inputs {
+            match pressed {
+                true => simulation.press(Directions::Up, *time),
+                false => simulation.release(Directions::Up, *time),
+            }
+        }
+        simulation.finish();
+        assert!(simulation.is_finished());
+        simulation
+    }
+
+    #[test]
+    fn holds_are_judged_when_pressed_and_released() {
+        let mut autoplay = Simulation::new(hold_song(), PlayMode::Autoplay, None);
+        autoplay.finish();
+        assert!(autoplay.is_finished());
+        assert_eq!(autoplay.score().count(Judgement::Perfect), 3);
+        assert_eq!(autoplay.score().max_combo(), 3);
+
+        // Letting go early misses the end of the hold, but not its start
+        let early = play_holds(&[(true, 1.), (false, 1.5), (true, 3.)]);
+        let score = early.score();
+        assert_eq!(score.count(Judgement::Perfect), 2);
+        assert_eq!(score.count(Judgement::Miss), 1);
+        assert_eq!(score.max_combo(), 1);
+
+        // Letting go inside the threshold of the end, or not at all, holds it
+        let layout = Layout::default();
+        let hit_window = (layout.threshold / layout.speed(Speed::Medium)) as f64;
+        let barely = play_holds(&[(true, 1.), (false, 2. - hit_window / 2.), (true, 3.)]);
+        assert_eq!(barely.score().count(Judgement::Perfect), 3);
+        let never = play_holds(&[(true, 1.), (true, 3.)]);
+        assert_eq!(never.score().count(Judgement::Perfect), 3);
+
+        // Missing the start misses the whole hold
+        let missed = play_holds(&[]);
+        assert_eq!(missed.score().count(Judgement::Miss), 3);
+    }
+}
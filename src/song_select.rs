@@ -0,0 +1,758 @@
+use crate::arrows::PlayMode;
+use crate::consts::*;
+use crate::high_scores::HighScores;
+use crate::library;
+use crate::menu::ButtonMaterials;
+use crate::preview::{stop_preview, PreviewPlayer};
+use crate::replay::{Replay, WatchReplay};
+use crate::sfx::{PlaySfx, Sfx};
+use crate::stats::ChartStats;
+use crate::types::{load_config, parse_config, SongConfig};
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    render::texture::ImageType,
+    tasks::AsyncComputeTaskPool,
+    utils::HashMap,
+    window::ReceivedCharacter,
+};
+use std::cmp::Ordering;
+
+/// Information about a chart shown in the song list
+pub struct SongEntry {
+    /// Path of the chart in `assets/songs`, without its extension
+    pub chart: String,
+    pub pack: Option<String>,
+    /// Hash of the notes, used to find the personal best
+    pub chart_hash: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub difficulty: Option<String>,
+    pub level: Option<u32>,
+    pub bpm: Option<f64>,
+    /// Time of the last note, in seconds
+    pub length: f64,
+    pub stats: ChartStats,
+    pub filename: String,
+    pub preview_start: f64,
+}
+impl SongEntry {
+    pub fn load(chart: &str) -> Result<Self, String> {
+        let config = parse_config(&format!("{}.toml", chart))?;
+        Ok(Self {
+            chart: chart.to_string(),
+            pack: library::pack(chart),
+            chart_hash: config.chart_hash(),
+            length: config.length(),
+            stats: ChartStats::new(&SongConfig::new(chart, config.clone())),
+            preview_start: config.preview_start(),
+            title: config.name,
+            artist: config.artist,
+            difficulty: config.difficulty,
+            level: config.level,
+            bpm: config.bpm,
+            filename: library::resolve(chart, &config.filename),
+        })
+    }
+
+    /// Checks if the title, artist or difficulty contain `search`, ignoring case
+    fn matches(&self, search: &str) -> bool {
+        let search = search.to_lowercase();
+        let fields = [
+            Some(&self.title),
+            self.artist.as_ref(),
+            self.difficulty.as_ref(),
+        ];
+        fields
+            .iter()
+            .flatten()
+            .any(|field| field.to_lowercase().contains(&search))
+    }
+}
+
+/// Orders the song list can be sorted by
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SortMode {
+    Title,
+    Artist,
+    Bpm,
+    Length,
+    Level,
+}
+impl SortMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortMode::Title => "Title",
+            SortMode::Artist => "Artist",
+            SortMode::Bpm => "BPM",
+            SortMode::Length => "Length",
+            SortMode::Level => "Level",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            SortMode::Title => SortMode::Artist,
+            SortMode::Artist => SortMode::Bpm,
+            SortMode::Bpm => SortMode::Length,
+            SortMode::Length => SortMode::Level,
+            SortMode::Level => SortMode::Title,
+        }
+    }
+
+    fn compare(&self, a: &SongEntry, b: &SongEntry) -> Ordering {
+        // Songs without a value go last
+        fn by<T: PartialOrd>(a: Option<T>, b: Option<T>) -> Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        }
+        let title = || a.title.to_lowercase().cmp(&b.title.to_lowercase());
+        // Songs are grouped by pack, and sorted inside each pack
+        let pack = by(a.pack.as_ref(), b.pack.as_ref());
+        let sorted = match self {
+            SortMode::Title => title().then(by(a.level, b.level)),
+            SortMode::Artist => by(
+                a.artist.as_ref().map(|artist| artist.to_lowercase()),
+                b.artist.as_ref().map(|artist| artist.to_lowercase()),
+            )
+            .then_with(title),
+            SortMode::Bpm => by(a.bpm, b.bpm).then_with(title),
+            SortMode::Length => by(Some(a.length), Some(b.length)).then_with(title),
+            SortMode::Level => by(a.level, b.level).then_with(title),
+        };
+        pack.then(sorted)
+    }
+}
+
+/// Songs in the song list, and the pack, search and sort used to show them
+pub struct SongSelect {
+    entries: Vec<SongEntry>,
+    /// Names of the packs, sorted
+    packs: Vec<String>,
+    /// Index in `packs` of the only pack shown, or `None` to show every song
+    pack: Option<usize>,
+    /// Indices into `entries` of the songs that match the search, in order
+    visible: Vec<usize>,
+    pub search: String,
+    pub sort: SortMode,
+    /// Position in `visible` of the selected song
+    selected: usize,
+    /// Play songs with autoplay instead of pressing the keys
+    pub autoplay: bool,
+}
+impl Default for SongSelect {
+    fn default() -> Self {
+        Self {
+            entries: vec![],
+            packs: vec![],
+            pack: None,
+            visible: vec![],
+            search: String::new(),
+            sort: SortMode::Title,
+            selected: 0,
+            autoplay: false,
+        }
+    }
+}
+impl SongSelect {
+    /// Reads every chart again, keeping the selected song if it's still there
+    pub fn load(&mut self) {
+        let selected = self.selected().map(|entry| entry.chart.clone());
+        let pack = self.pack_name().map(str::to_string);
+        self.entries = library::find_charts()
+            .iter()
+            // Charts that can't be read, like ones made for a newer version of the game, are left out
+            .filter_map(|chart| match SongEntry::load(chart) {
+                Ok(entry) => Some(entry),
+                Err(error) => {
+                    warn!("Couldn't load chart {}: {}", chart, error);
+                    None
+                }
+            })
+            .collect();
+
+        self.packs = self
+            .entries
+            .iter()
+            .filter_map(|entry| entry.pack.clone())
+            .collect();
+        self.packs.sort();
+        self.packs.dedup();
+        self.pack = pack.and_then(|pack| self.packs.iter().position(|name| *name == pack));
+
+        // The old positions don't point to the same songs anymore
+        self.visible.clear();
+        self.refresh();
+        if let Some(chart) = selected {
+            self.select_chart(&chart);
+        }
+    }
+
+    /// Filters and sorts the songs after the search or sort changed
+    pub fn refresh(&mut self) {
+        let selected = self.selected().map(|entry| entry.chart.clone());
+
+        let entries = &self.entries;
+        let pack = self.pack_name();
+        self.visible = (0..entries.len())
+            .filter(|i| pack.is_none() || entries[*i].pack.as_deref() == pack)
+            .filter(|i| entries[*i].matches(&self.search))
+            .collect();
+        let sort = self.sort;
+        self.visible
+            .sort_by(|a, b| sort.compare(&entries[*a], &entries[*b]));
+
+        self.selected = 0;
+        if let Some(chart) = selected {
+            self.select_chart(&chart);
+        }
+    }
+
+    /// Name of the only pack shown, if there's one
+    pub fn pack_name(&self) -> Option<&str> {
+        self.pack.map(|pack| self.packs[pack].as_str())
+    }
+
+    /// Cycles through showing every song and showing each pack, returning true if it changed
+    pub fn change_pack(&mut self, change: isize) -> bool {
+        if self.packs.is_empty() {
+            return false;
+        }
+        // Position 0 shows every song, and the rest are the packs
+        let options = self.packs.len() as isize + 1;
+        let current = self.pack.map(|pack| pack as isize + 1).unwrap_or(0);
+        let new = (current + change).rem_euclid(options);
+        self.pack = if new == 0 {
+            None
+        } else {
+            Some(new as usize - 1)
+        };
+        self.refresh();
+        true
+    }
+
+    fn select_chart(&mut self, chart: &str) {
+        if let Some(position) = self
+            .visible
+            .iter()
+            .position(|i| self.entries[*i].chart == chart)
+        {
+            self.selected = position;
+        }
+    }
+
+    pub fn selected(&self) -> Option<&SongEntry> {
+        self.visible.get(self.selected).map(|i| &self.entries[*i])
+    }
+
+    /// Moves the selection by `change` songs, returning true if it moved
+    pub fn move_selection(&mut self, change: isize) -> bool {
+        if self.visible.is_empty() {
+            return false;
+        }
+        let last = self.visible.len() as isize - 1;
+        let new = (self.selected as isize + change).max(0).min(last) as usize;
+        let moved = new != self.selected;
+        self.selected = new;
+        moved
+    }
+
+    /// Position in the list of the song shown in the first row
+    fn first_row(&self) -> usize {
+        let max = self.visible.len().saturating_sub(SONG_SELECT_ROWS);
+        self.selected.saturating_sub(SONG_SELECT_ROWS / 2).min(max)
+    }
+
+    /// Returns the song shown in a row, if there's one
+    fn row(&self, row: usize) -> Option<&SongEntry> {
+        self.visible
+            .get(self.first_row() + row)
+            .map(|i| &self.entries[*i])
+    }
+}
+
+/// Formats seconds as minutes and seconds
+fn format_length(seconds: f64) -> String {
+    let seconds = seconds.max(0.) as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Root of the song list
+struct SongSelectUI;
+/// Row of the song list, showing the song at that position from the top of the list
+struct SongRow(usize);
+struct SongRowText;
+/// Text with the search, sort and controls
+struct SongSelectHeader;
+/// Text with the information of the selected song
+struct SongDetails;
+/// Image above the details of the selected song
+struct SongBanner;
+
+fn setup_song_select(
+    mut commands: Commands,
+    button_materials: Res<ButtonMaterials>,
+    mut song_select: ResMut<SongSelect>,
+) {
+    song_select.load();
+
+    let text = |value: &str, font_size: f32| {
+        Text::with_section(
+            value,
+            TextStyle {
+                font: button_materials.font.clone(),
+                font_size,
+                color: Color::rgb(0.9, 0.9, 0.9),
+            },
+            Default::default(),
+        )
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    top: Val::Px(10.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            material: button_materials.none.clone(),
+            ..Default::default()
+        })
+        .insert(SongSelectUI)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: text("", 20.),
+                    style: Style {
+                        margin: Rect::all(Val::Px(5.)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(SongSelectHeader);
+
+            for row in 0..SONG_SELECT_ROWS {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(400.), Val::Px(50.)),
+                            margin: Rect::all(Val::Px(2.)),
+                            padding: Rect::all(Val::Px(10.)),
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        material: button_materials.normal.clone(),
+                        ..Default::default()
+                    })
+                    .insert(SongRow(row))
+                    .with_children(|parent| {
+                        parent
+                            .spawn_bundle(TextBundle {
+                                text: text("", 20.),
+                                ..Default::default()
+                            })
+                            .insert(SongRowText);
+                    });
+            }
+        });
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    top: Val::Px(10.),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Px(350.), Val::Auto),
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            material: button_materials.none.clone(),
+            ..Default::default()
+        })
+        .insert(SongSelectUI)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(ImageBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(350.), Val::Px(110.)),
+                        display: Display::None,
+                        ..Default::default()
+                    },
+                    material: button_materials.none.clone(),
+                    ..Default::default()
+                })
+                .insert(SongBanner);
+            parent
+                .spawn_bundle(TextBundle {
+                    text: text("", 20.),
+                    ..Default::default()
+                })
+                .insert(SongDetails);
+        });
+}
+
+/// Banner of each song that has been selected, or `None` if it doesn't have one
+#[derive(Default)]
+struct Banners {
+    materials: HashMap<String, Option<Handle<ColorMaterial>>>,
+}
+
+/// Shows the banner of the selected song, hiding the image if it doesn't have one
+fn update_banner(
+    song_select: Res<SongSelect>,
+    mut banners: ResMut<Banners>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut query: Query<(&mut Style, &mut Handle<ColorMaterial>), With<SongBanner>>,
+) {
+    if !song_select.is_changed() {
+        return;
+    }
+
+    let banner = song_select.selected().and_then(|entry| {
+        banners
+            .materials
+            .entry(entry.chart.clone())
+            .or_insert_with(|| {
+                let bytes = library::read_banner(&entry.chart)?;
+                let texture = Texture::from_buffer(&bytes, ImageType::Extension("png")).ok()?;
+                Some(materials.add(textures.add(texture).into()))
+            })
+            .clone()
+    });
+
+    for (mut style, mut material) in query.iter_mut() {
+        match &banner {
+            Some(banner) => {
+                style.display = Display::Flex;
+                *material = banner.clone();
+            }
+            None => style.display = Display::None,
+        }
+    }
+}
+
+/// Moves the selection with the arrow keys, the gamepad or the mouse wheel, and changes the pack and sort
+fn navigate_song_select(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut wheel_events: EventReader<MouseWheel>,
+    // Rows scrolled that didn't add up to a whole row yet
+    mut scrolled: Local<f32>,
+    mut song_select: ResMut<SongSelect>,
+    mut sfx: EventWriter<PlaySfx>,
+) {
+    let mut change = 0;
+    let mut pack_change = 0;
+    let mut next_sort = false;
+
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        change -= 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        change += 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::PageUp) {
+        change -= SONG_SELECT_ROWS as isize;
+    }
+    if keyboard_input.just_pressed(KeyCode::PageDown) {
+        change += SONG_SELECT_ROWS as isize;
+    }
+    if keyboard_input.just_pressed(KeyCode::Left) {
+        pack_change -= 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::Right) {
+        pack_change += 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        next_sort = true;
+    }
+    for button in gamepad_input.get_just_pressed() {
+        match button.1 {
+            GamepadButtonType::DPadUp => change -= 1,
+            GamepadButtonType::DPadDown => change += 1,
+            GamepadButtonType::DPadLeft => pack_change -= 1,
+            GamepadButtonType::DPadRight => pack_change += 1,
+            GamepadButtonType::LeftTrigger => change -= SONG_SELECT_ROWS as isize,
+            GamepadButtonType::RightTrigger => change += SONG_SELECT_ROWS as isize,
+            GamepadButtonType::North => next_sort = true,
+            _ => {}
+        }
+    }
+    // Mice scroll in lines and touchpads in pixels, which add up until they're a whole row
+    for event in wheel_events.iter() {
+        *scrolled += match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / SONG_SELECT_SCROLL_PIXELS,
+        };
+    }
+    let rows = scrolled.trunc();
+    *scrolled -= rows;
+    change -= rows as isize;
+
+    if pack_change != 0 && song_select.change_pack(pack_change) {
+        sfx.send(PlaySfx(Sfx::MenuMove));
+    }
+    if next_sort {
+        song_select.sort = song_select.sort.next();
+        song_select.refresh();
+        sfx.send(PlaySfx(Sfx::MenuMove));
+    }
+    if change != 0 && song_select.move_selection(change) {
+        sfx.send(PlaySfx(Sfx::MenuMove));
+    }
+}
+
+/// Writes the typed characters into the search
+fn type_search(
+    mut char_events: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut song_select: ResMut<SongSelect>,
+) {
+    let mut search = song_select.search.clone();
+    for event in char_events.iter() {
+        if !event.char.is_control() {
+            search.push(event.char);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        search.pop();
+    }
+
+    if search != song_select.search {
+        song_select.search = search;
+        song_select.refresh();
+    }
+}
+
+/// Selects the row under the mouse, and plays a row's song when it's clicked
+fn song_row_mouse(
+    query: Query<(&Interaction, &SongRow), Changed<Interaction>>,
+    mut song_select: ResMut<SongSelect>,
+    mut play: EventWriter<PlaySelectedSong>,
+) {
+    for (interaction, SongRow(row)) in query.iter() {
+        let position = song_select.first_row() + row;
+        if *interaction == Interaction::None || position >= song_select.visible.len() {
+            continue;
+        }
+        song_select.selected = position;
+        if *interaction == Interaction::Clicked {
+            play.send(PlaySelectedSong);
+        }
+    }
+}
+
+/// Event to start playing the selected song
+struct PlaySelectedSong;
+
+fn confirm_song(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut play: EventWriter<PlaySelectedSong>,
+    mut sfx: EventWriter<PlaySfx>,
+) {
+    let gamepad_confirm = gamepad_input
+        .get_just_pressed()
+        .any(|button| button.1 == GamepadButtonType::South);
+    if keyboard_input.just_pressed(KeyCode::Return) || gamepad_confirm {
+        play.send(PlaySelectedSong);
+        // Clicking a row already plays the button sound
+        sfx.send(PlaySfx(Sfx::MenuConfirm));
+    }
+}
+
+fn play_selected_song(
+    mut commands: Commands,
+    mut events: EventReader<PlaySelectedSong>,
+    song_select: Res<SongSelect>,
+    mut mode: ResMut<PlayMode>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if events.iter().next().is_none() {
+        return;
+    }
+    if let Some(entry) = song_select.selected() {
+        let config = load_config(&format!("{}.toml", entry.chart));
+        commands.insert_resource(config);
+        *mode = if song_select.autoplay {
+            PlayMode::Autoplay
+        } else {
+            PlayMode::Play
+        };
+        state
+            .set(AppState::Game)
+            .expect("Couldn't switch state to Game");
+    }
+}
+
+/// Watches the last replay of the selected chart with F3, and toggles autoplay with F4
+fn replay_keys(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut song_select: ResMut<SongSelect>,
+    mut watch: EventWriter<WatchReplay>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F4) {
+        song_select.autoplay = !song_select.autoplay;
+    }
+    if !keyboard_input.just_pressed(KeyCode::F3) {
+        return;
+    }
+    let replay = song_select
+        .selected()
+        .and_then(|entry| Replay::latest(&entry.chart, &entry.chart_hash));
+    if let Some(replay) = replay {
+        watch.send(WatchReplay(replay));
+    }
+}
+
+fn update_song_rows(
+    song_select: Res<SongSelect>,
+    button_materials: Res<ButtonMaterials>,
+    mut rows: Query<(&SongRow, &Children, &mut Handle<ColorMaterial>)>,
+    mut texts: Query<&mut Text, With<SongRowText>>,
+) {
+    let first_row = song_select.first_row();
+    for (SongRow(row), children, mut material) in rows.iter_mut() {
+        let entry = song_select.row(*row);
+        *material = if first_row + row == song_select.selected && entry.is_some() {
+            button_materials.hovered.clone()
+        } else if entry.is_some() {
+            button_materials.normal.clone()
+        } else {
+            button_materials.none.clone()
+        };
+
+        let value = match entry {
+            Some(entry) => match &entry.difficulty {
+                Some(difficulty) => format!("{} [{}]", entry.title, difficulty),
+                None => entry.title.clone(),
+            },
+            None => String::new(),
+        };
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                text.sections[0].value = value.clone();
+            }
+        }
+    }
+}
+
+fn update_song_select_texts(
+    song_select: Res<SongSelect>,
+    high_scores: Res<HighScores>,
+    mut headers: Query<&mut Text, (With<SongSelectHeader>, Without<SongDetails>)>,
+    mut details: Query<&mut Text, (With<SongDetails>, Without<SongSelectHeader>)>,
+) {
+    // The texts are empty until the first update, so they can't wait for a change
+
+    for mut text in headers.iter_mut() {
+        text.sections[0].value = format!(
+            "Pack: {} (Left/Right)\nSearch: {}_\nSort: {} (Tab)\n{} of {} songs. Enter: play\nF1: settings. F2: make map. F3: watch replay\nAutoplay: {} (F4)",
+            song_select.pack_name().unwrap_or("All"),
+            song_select.search,
+            song_select.sort.name(),
+            song_select.visible.len(),
+            song_select.entries.len(),
+            if song_select.autoplay { "on" } else { "off" },
+        );
+    }
+
+    let value = match song_select.selected() {
+        Some(entry) => {
+            let optional = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+            format!(
+                "{}\nPack: {}\nArtist: {}\nDifficulty: {}\nLevel: {}\nBPM: {}\nLength: {}\nNotes: {} ({:.1}/s, peak {:.1}/s)\nChords: {:.0}%. Jacks: {}. Streams: {}\nRating: {:.1}\nBest: {}",
+                entry.title,
+                optional(entry.pack.clone()),
+                optional(entry.artist.clone()),
+                optional(entry.difficulty.clone()),
+                optional(entry.level.map(|level| level.to_string())),
+                optional(entry.bpm.map(|bpm| format!("{:.0}", bpm))),
+                format_length(entry.length),
+                entry.stats.notes,
+                entry.stats.average_nps,
+                entry.stats.peak_nps,
+                entry.stats.chord_ratio * 100.,
+                entry.stats.jacks,
+                entry.stats.stream_presses,
+                entry.stats.rating,
+                optional(
+                    high_scores
+                        .best(&entry.chart, &entry.chart_hash)
+                        .map(|best| format!(
+                            "{} ({:.2}%) on {}",
+                            best.score,
+                            best.accuracy * 100.,
+                            best.date()
+                        ))
+                ),
+            )
+        }
+        None => "No songs found".to_string(),
+    };
+    for mut text in details.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
+
+/// Plays a preview of the selected song
+fn preview_selected_song(
+    song_select: Res<SongSelect>,
+    pool: Res<AsyncComputeTaskPool>,
+    mut player: NonSendMut<PreviewPlayer>,
+) {
+    match song_select.selected() {
+        Some(entry) if player.is_playing(&entry.chart) => {}
+        Some(entry) => player.play(&pool, &entry.chart, &entry.filename, entry.preview_start),
+        None => player.stop(),
+    }
+}
+
+fn despawn_song_select(mut commands: Commands, query: Query<Entity, With<SongSelectUI>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub struct SongSelectPlugin;
+impl Plugin for SongSelectPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SongSelect>()
+            .init_resource::<Banners>()
+            .add_event::<PlaySelectedSong>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Menu).with_system(setup_song_select.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Menu)
+                    .with_system(navigate_song_select.system())
+                    .with_system(type_search.system())
+                    .with_system(song_row_mouse.system())
+                    .with_system(confirm_song.system())
+                    .with_system(play_selected_song.system())
+                    .with_system(replay_keys.system())
+                    .with_system(update_song_rows.system())
+                    .with_system(update_song_select_texts.system())
+                    .with_system(update_banner.system())
+                    .with_system(preview_selected_song.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Menu)
+                    .with_system(despawn_song_select.system())
+                    .with_system(stop_preview.system()),
+            );
+    }
+}
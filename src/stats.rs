@@ -0,0 +1,216 @@
+use crate::cli::charts_or_all;
+use crate::consts::*;
+use crate::types::{parse_config, ArrowTime, SongConfig};
+use std::process;
+
+/// Numbers that describe how hard a chart is to play
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChartStats {
+    pub notes: usize,
+    /// Notes per second from the first note to the last
+    pub average_nps: f64,
+    /// Most notes per second in any `STATS_WINDOW` seconds of the chart
+    pub peak_nps: f64,
+    /// Fraction of presses that hit more than one lane at once
+    pub chord_ratio: f64,
+    /// Presses that repeat a lane of the press right before them, quickly
+    pub jacks: usize,
+    /// Presses in runs of at least `STREAM_LENGTH` quick presses that keep changing lanes
+    pub stream_presses: usize,
+    /// Estimated difficulty, roughly the notes per second a player has to keep up with
+    pub rating: f64,
+}
+impl ChartStats {
+    pub fn new(config: &SongConfig) -> Self {
+        let notes = config.arrows.len();
+        if notes == 0 {
+            return Self::default();
+        }
+
+        let times = &config.click_times;
+        let length = times[notes - 1] - times[0];
+        let average_nps = notes as f64 / length.max(STATS_WINDOW);
+        let peak_notes = (0..notes)
+            .map(|start| {
+                times[start..]
+                    .iter()
+                    .take_while(|time| **time < times[start] + STATS_WINDOW)
+                    .count()
+            })
+            .max()
+            .unwrap_or(0);
+        let peak_nps = peak_notes as f64 / STATS_WINDOW;
+
+        let presses = presses(&config.arrows);
+        let chords = presses.iter().filter(|press| press.lanes.len() > 1).count();
+        let chord_ratio = chords as f64 / presses.len() as f64;
+
+        let mut jacks = 0;
+        let mut stream_presses = 0;
+        // Presses in the current run of quick presses that change lanes
+        let mut run = 1;
+        for pair in presses.windows(2) {
+            let quick = pair[1].time - pair[0].time <= QUICK_PRESS_GAP;
+            let repeats = pair[1]
+                .lanes
+                .iter()
+                .any(|lane| pair[0].lanes.contains(lane));
+            if quick && repeats {
+                jacks += 1;
+            }
+
+            if quick && !repeats {
+                run += 1;
+            } else {
+                if run >= STREAM_LENGTH {
+                    stream_presses += run;
+                }
+                run = 1;
+            }
+        }
+        if run >= STREAM_LENGTH {
+            stream_presses += run;
+        }
+
+        // Peaks matter more than the average, and chords, jacks and streams are harder than
+        // the same notes spread out
+        let density = 0.7 * peak_nps + 0.3 * average_nps;
+        let patterns = 1.
+            + 0.5 * chord_ratio
+            + 0.5 * jacks as f64 / presses.len() as f64
+            + 0.25 * stream_presses as f64 / presses.len() as f64;
+
+        Self {
+            notes,
+            average_nps,
+            peak_nps,
+            chord_ratio,
+            jacks,
+            stream_presses,
+            rating: density * patterns,
+        }
+    }
+}
+
+/// Notes hit at the same time
+struct Press {
+    time: f64,
+    lanes: Vec<usize>,
+}
+
+/// Groups notes sorted by time into presses
+fn presses(arrows: &[ArrowTime]) -> Vec<Press> {
+    let mut presses: Vec<Press> = vec![];
+    for arrow in arrows.iter() {
+        match presses.last_mut() {
+            Some(press) if arrow.click_time - press.time <= CHORD_MARGIN => {
+                press.lanes.push(arrow.direction as usize)
+            }
+            _ => presses.push(Press {
+                time: arrow.click_time,
+                lanes: vec![arrow.direction as usize],
+            }),
+        }
+    }
+    presses
+}
+
+const USAGE: &str = "Usage: bevy_rhythm stats [chart...]
+
+Prints the note density, patterns and estimated difficulty of charts in `assets/songs`.
+Without charts, prints them for every chart in `assets/songs`.";
+
+/// Prints chart statistics from the command line
+pub fn run_cli(args: &[String]) {
+    let mut failed = false;
+    for chart in charts_or_all(args, USAGE) {
+        let config = match parse_config(&format!("{}.toml", chart)) {
+            Ok(config) => SongConfig::new(&chart, config),
+            Err(error) => {
+                println!("{}: Couldn't parse chart: {}", chart, error);
+                failed = true;
+                continue;
+            }
+        };
+        let stats = ChartStats::new(&config);
+        println!(
+            "{}: rating {:.1}. {} notes, {:.1}/s average, {:.1}/s peak. Chords: {:.0}%. Jacks: {}. Stream presses: {}",
+            chart,
+            stats.rating,
+            stats.notes,
+            stats.average_nps,
+            stats.peak_nps,
+            stats.chord_ratio * 100.,
+            stats.jacks,
+            stats.stream_presses,
+        );
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::test_charts::{chart, note};
+    use crate::types::Directions::{self, *};
+
+    /// A chart of taps
+    fn config(notes: &[(f64, Directions)]) -> SongConfig {
+        chart(
+            notes
+                .iter()
+                .map(|(click_time, direction)| note(*click_time, *direction, None))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn notes_close_together_are_one_press() {
+        let config = config(&[(1., Up), (1. + CHORD_MARGIN / 2., Down), (1.02, Left)]);
+        let presses = presses(&config.arrows);
+        assert_eq!(presses.len(), 2);
+        assert_eq!(presses[0].time, 1.);
+        assert_eq!(presses[0].lanes, vec![Up as usize, Down as usize]);
+        assert_eq!(presses[1].lanes, vec![Left as usize]);
+    }
+
+    #[test]
+    fn jacks_and_streams_are_counted() {
+        let stats = ChartStats::new(&config(&[
+            // A stream of four presses that change lanes, ended by a jack
+            (0., Left),
+            (0.1, Down),
+            (0.2, Up),
+            (0.3, Right),
+            (0.4, Right),
+            // A chord, then a jack on one of its lanes
+            (2., Left),
+            (2., Right),
+            (2.1, Left),
+        ]));
+        assert_eq!(stats.notes, 8);
+        assert_eq!(stats.jacks, 2);
+        assert_eq!(stats.stream_presses, 4);
+        assert_eq!(stats.chord_ratio, 1. / 7.);
+    }
+
+    #[test]
+    fn charts_without_notes_have_no_stats() {
+        assert_eq!(ChartStats::new(&config(&[])), ChartStats::default());
+    }
+
+    #[test]
+    fn single_notes_are_spread_over_the_window() {
+        let stats = ChartStats::new(&config(&[(5., Up)]));
+        assert_eq!(stats.notes, 1);
+        assert_eq!(stats.average_nps, 1. / STATS_WINDOW);
+        assert_eq!(stats.peak_nps, 1. / STATS_WINDOW);
+        assert_eq!(stats.chord_ratio, 0.);
+        assert_eq!(stats.jacks, 0);
+        assert_eq!(stats.stream_presses, 0);
+        assert!(stats.rating > 0.);
+    }
+}
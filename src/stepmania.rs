@@ -0,0 +1,735 @@
+use crate::consts::*;
+use crate::library;
+use crate::types::{ArrowTimeToml, BeatGrid, Directions, SongConfigToml};
+
+// StepMania simfiles are lists of `#TAG:value;`. `.sm` files have a `#NOTES` tag for each chart,
+// and `.ssc` files a `#NOTEDATA` tag followed by the tags of the chart, which can have its own timing.
+// Imported charts are named by their file and difficulty, like `song/song.sm#Hard`.
+
+/// Lanes of `dance-single` charts, in the order they're written
+const LANES: [Directions; 4] = [
+    Directions::Left,
+    Directions::Down,
+    Directions::Up,
+    Directions::Right,
+];
+
+/// Rows each beat is split into when exporting, which StepMania calls 192nd notes
+const ROWS_PER_BEAT: usize = 48;
+
+/// Rows a measure can be written with, from fewest to most
+const MEASURE_ROWS: [usize; 10] = [4, 8, 12, 16, 24, 32, 48, 64, 96, 192];
+
+/// Checks if a file is a StepMania simfile
+pub fn is_simfile(file: &str) -> bool {
+    file.ends_with(".sm") || file.ends_with(".ssc")
+}
+
+/// Tempo changes and stops, which turn beats into seconds
+#[derive(Clone, Debug, Default)]
+struct Timing {
+    /// Seconds before the first beat, negated
+    offset: f64,
+    /// Beats where the tempo changes, and the new BPM
+    bpms: Vec<(f64, f64)>,
+    /// Beats where the song stops, and for how many seconds
+    stops: Vec<(f64, f64)>,
+}
+impl Timing {
+    fn check(&self) -> Result<(), String> {
+        if self.bpms.is_empty() {
+            return Err("The chart has no BPM".to_string());
+        }
+        if self.bpms.iter().any(|(_, bpm)| *bpm <= 0.) {
+            return Err("Charts with negative BPMs can't be imported".to_string());
+        }
+        Ok(())
+    }
+
+    /// Returns the time in seconds at which a beat is hit
+    fn time_at(&self, beat: f64) -> f64 {
+        let mut time = -self.offset;
+        let (mut last_beat, mut bpm) = (0., self.bpms[0].1);
+        for (change, next_bpm) in self.bpms.iter().skip(1) {
+            if *change >= beat {
+                break;
+            }
+            time += (change - last_beat) * 60. / bpm;
+            last_beat = *change;
+            bpm = *next_bpm;
+        }
+        time += (beat - last_beat) * 60. / bpm;
+
+        // Notes on a stop are hit before it
+        let stopped: f64 = self
+            .stops
+            .iter()
+            .filter(|(stop, _)| *stop < beat)
+            .map(|(_, seconds)| seconds)
+            .sum();
+        time + stopped
+    }
+}
+
+#[derive(Default)]
+struct SimfileChart {
+    steps_type: String,
+    difficulty: String,
+    meter: Option<u32>,
+    /// Timing of `.ssc` charts that don't use the song's
+    timing: Option<Timing>,
+    notes: String,
+}
+
+#[derive(Default)]
+struct Simfile {
+    title: String,
+    artist: Option<String>,
+    music: String,
+    sample_start: Option<f64>,
+    timing: Timing,
+    charts: Vec<SimfileChart>,
+}
+impl Simfile {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut simfile = Simfile::default();
+        // Chart of a `.ssc` file whose tags are being read
+        let mut current: Option<SimfileChart> = None;
+
+        for (tag, value) in tags(text) {
+            if let Some(chart) = current.as_mut() {
+                match tag.as_str() {
+                    "STEPSTYPE" => chart.steps_type = value,
+                    "DIFFICULTY" => chart.difficulty = value,
+                    "METER" => chart.meter = value.parse().ok(),
+                    "OFFSET" | "BPMS" | "STOPS" | "FREEZES" => {
+                        let timing = chart.timing.get_or_insert_with(|| simfile.timing.clone());
+                        read_timing(timing, &tag, &value)?;
+                    }
+                    "NOTES" => {
+                        chart.notes = value;
+                        simfile.charts.extend(current.take());
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            match tag.as_str() {
+                "TITLE" => simfile.title = value,
+                "ARTIST" if !value.is_empty() => simfile.artist = Some(value),
+                "MUSIC" => simfile.music = value,
+                "SAMPLESTART" => simfile.sample_start = value.parse().ok(),
+                "OFFSET" | "BPMS" | "STOPS" | "FREEZES" => {
+                    read_timing(&mut simfile.timing, &tag, &value)?
+                }
+                "NOTEDATA" => current = Some(SimfileChart::default()),
+                "NOTES" => {
+                    // `.sm` charts are `type:description:difficulty:meter:groove radar:notes`
+                    let fields: Vec<&str> = value.splitn(6, ':').map(str::trim).collect();
+                    if fields.len() < 6 {
+                        return Err("Couldn't parse #NOTES".to_string());
+                    }
+                    simfile.charts.push(SimfileChart {
+                        steps_type: fields[0].to_string(),
+                        difficulty: fields[2].to_string(),
+                        meter: fields[3].parse().ok(),
+                        timing: None,
+                        notes: fields[5].to_string(),
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(simfile)
+    }
+
+    /// Returns the name of each chart that can be played with four lanes, and the chart
+    fn single_charts(&self) -> Vec<(String, &SimfileChart)> {
+        let mut charts: Vec<(String, &SimfileChart)> = vec![];
+        let mut difficulties: Vec<&str> = vec![];
+        for chart in self.charts.iter() {
+            if !chart.steps_type.eq_ignore_ascii_case("dance-single") {
+                continue;
+            }
+            let difficulty = match chart.difficulty.as_str() {
+                "" => "Edit",
+                difficulty => difficulty,
+            };
+            // Songs can have many Edit charts
+            let repeated = difficulties
+                .iter()
+                .filter(|other| **other == difficulty)
+                .count();
+            let name = match repeated {
+                0 => difficulty.to_string(),
+                _ => format!("{} {}", difficulty, repeated + 1),
+            };
+            difficulties.push(difficulty);
+            charts.push((name, chart));
+        }
+        charts
+    }
+
+    /// Converts one of the charts into the chart format of the game
+    fn import(&self, name: &str) -> Result<SongConfigToml, String> {
+        let chart = self
+            .single_charts()
+            .into_iter()
+            .find(|(chart_name, _)| chart_name == name)
+            .map(|(_, chart)| chart)
+            .ok_or_else(|| format!("The simfile has no {} chart", name))?;
+        if self.music.is_empty() {
+            return Err("The simfile has no #MUSIC".to_string());
+        }
+        let timing = chart.timing.as_ref().unwrap_or(&self.timing);
+        timing.check()?;
+
+        Ok(SongConfigToml {
+            format_version: CHART_FORMAT_VERSION,
+            name: self.title.clone(),
+            artist: self.artist.clone(),
+            difficulty: Some(name.to_string()),
+            level: chart.meter,
+            filename: self.music.clone(),
+            bpm: Some(timing.bpms[0].1),
+            offset: Some(timing.time_at(0.)),
+            preview_start: self.sample_start,
+            arrows: read_notes(&chart.notes, timing)?,
+        })
+    }
+}
+
+/// Splits a simfile into its tags, in uppercase, and their values
+fn tags(text: &str) -> Vec<(String, String)> {
+    let text: Vec<&str> = text
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or(""))
+        .collect();
+    let text = text.join("\n");
+
+    let mut tags = vec![];
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find('#') {
+        rest = &rest[start + 1..];
+        // Tags end with `;`, but some files forget it before the next tag
+        let end = [rest.find(';'), rest.find("\n#")]
+            .iter()
+            .flatten()
+            .copied()
+            .min()
+            .unwrap_or(rest.len());
+        let (tag, value) = rest[..end].split_once(':').unwrap_or((&rest[..end], ""));
+        tags.push((tag.trim().to_uppercase(), value.trim().to_string()));
+        rest = &rest[end..];
+    }
+    tags
+}
+
+/// Reads a list of `beat=value` pairs into the timing
+fn read_timing(timing: &mut Timing, tag: &str, value: &str) -> Result<(), String> {
+    let error = || format!("Couldn't parse #{}", tag);
+    if tag == "OFFSET" {
+        timing.offset = value.parse().map_err(|_| error())?;
+        return Ok(());
+    }
+
+    let mut pairs = vec![];
+    for pair in value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+    {
+        let (beat, value) = pair.split_once('=').ok_or_else(error)?;
+        let beat: f64 = beat.trim().parse().map_err(|_| error())?;
+        let value: f64 = value.trim().parse().map_err(|_| error())?;
+        pairs.push((beat, value));
+    }
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+    match tag {
+        "BPMS" => timing.bpms = pairs,
+        _ => timing.stops = pairs,
+    }
+    Ok(())
+}
+
+/// Turns the measures of a chart into notes
+fn read_notes(notes: &str, timing: &Timing) -> Result<Vec<ArrowTimeToml>, String> {
+    let mut arrows: Vec<ArrowTimeToml> = vec![];
+    // Holds and rolls that haven't ended yet on each lane, as their index in `arrows`
+    let mut holding: [Option<usize>; 4] = [None; 4];
+    for (measure, rows) in notes.split(',').enumerate() {
+        let rows: Vec<&str> = rows
+            .lines()
+            .map(str::trim)
+            .filter(|row| !row.is_empty())
+            .collect();
+        for (row, lanes) in rows.iter().enumerate() {
+            if lanes.len() != LANES.len() {
+                return Err(format!("Measure {} doesn't have 4 lanes", measure + 1));
+            }
+            let beat = 4. * (measure as f64 + row as f64 / rows.len() as f64);
+            for (lane, note) in lanes.chars().enumerate() {
+                match note {
+                    // Rolls are imported as holds, and lifts as taps
+                    '1' | '2' | '4' | 'L' => {
+                        if matches!(note, '2' | '4') {
+                            if holding[lane].is_some() {
+                                return Err(format!(
+                                    "Measure {} starts a hold while one is held",
+                                    measure + 1
+                                ));
+                            }
+                            holding[lane] = Some(arrows.len());
+                        }
+                        arrows.push(ArrowTimeToml {
+                            click_time: timing.time_at(beat),
+                            speed: IMPORTED_SPEED,
+                            direction: LANES[lane],
+                            hold_end: None,
+                        });
+                    }
+                    '3' => {
+                        let hold = holding[lane].take().ok_or_else(|| {
+                            format!("Measure {} ends a hold that didn't start", measure + 1)
+                        })?;
+                        arrows[hold].hold_end = Some(timing.time_at(beat));
+                    }
+                    // Mines and fakes aren't pressed
+                    _ => {}
+                }
+            }
+        }
+    }
+    if holding.iter().any(Option::is_some) {
+        return Err("The chart has a hold that doesn't end".to_string());
+    }
+    Ok(arrows)
+}
+
+fn read_simfile(file: &str) -> Result<Simfile, String> {
+    let bytes = library::read_file(file).map_err(|error| error.to_string())?;
+    Simfile::parse(&String::from_utf8_lossy(&bytes))
+}
+
+/// Returns the names of the charts that can be imported from a simfile in `assets/songs`
+pub fn find_charts(file: &str) -> Vec<String> {
+    match read_simfile(file) {
+        Ok(simfile) => simfile
+            .single_charts()
+            .into_iter()
+            .map(|(name, _)| format!("{}#{}", file, name))
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Imports a chart named like `song/song.sm#Hard`, or returns `None` if it isn't named like one
+pub fn import_chart(chart: &str) -> Option<Result<SongConfigToml, String>> {
+    let (file, name) = chart.rsplit_once('#')?;
+    if !is_simfile(file) {
+        return None;
+    }
+    Some(read_simfile(file).and_then(|simfile| simfile.import(name)))
+}
+
+/// Imports every chart of a simfile in `assets/songs` that can be played with four lanes
+pub fn import_all(file: &str) -> Result<Vec<SongConfigToml>, String> {
+    let simfile = read_simfile(file)?;
+    let charts = simfile.single_charts();
+    if charts.is_empty() {
+        return Err("The simfile has no 4 panel charts".to_string());
+    }
+    charts
+        .iter()
+        .map(|(name, _)| simfile.import(name))
+        .collect()
+}
+
+/// Writes charts of the same song as a `.sm` simfile, with their notes snapped to the beat grid.
+/// Only the metadata of the first chart is written
+pub fn export(charts: &[SongConfigToml], grid: BeatGrid) -> String {
+    // Measures can't be negative, so the first beat is moved back before the first note
+    let mut grid = grid;
+    let first_beat = charts
+        .iter()
+        .flat_map(|chart| chart.arrows.iter())
+        .map(|arrow| grid.beat_at(arrow.click_time))
+        .fold(0., f64::min);
+    grid.offset -= (-first_beat / 4.).ceil() * 4. * grid.beat_duration();
+
+    let mut text = String::new();
+    if let Some(song) = charts.first() {
+        text += &format!("#TITLE:{};\n", escape(&song.name));
+        text += &format!(
+            "#ARTIST:{};\n",
+            escape(song.artist.as_deref().unwrap_or(""))
+        );
+        text += &format!("#MUSIC:{};\n", escape(&song.filename));
+        // Subtracted from 0 so that a zero offset isn't written as -0
+        text += &format!("#OFFSET:{};\n", 0. - grid.offset);
+        if let Some(preview_start) = song.preview_start {
+            text += &format!("#SAMPLESTART:{:.3};\n", preview_start);
+        }
+    }
+    text += &format!("#BPMS:0.000={};\n#STOPS:;\n", grid.bpm);
+
+    let mut difficulties = vec![];
+    for chart in charts.iter() {
+        let name = chart.difficulty.as_deref().unwrap_or("");
+        // Only Edit charts can be repeated
+        let difficulty = match difficulty_name(name) {
+            difficulty if difficulties.contains(&difficulty) => "Edit",
+            difficulty => difficulty,
+        };
+        difficulties.push(difficulty);
+        text += &format!(
+            "\n#NOTES:\n     dance-single:\n     {}:\n     {}:\n     {}:\n     0,0,0,0,0:\n{};\n",
+            escape(name).replace(':', ""),
+            difficulty,
+            chart.level.unwrap_or(1),
+            write_notes(&chart.arrows, &grid),
+        );
+    }
+    text
+}
+
+/// Returns the StepMania difficulty closest to a chart's difficulty name
+fn difficulty_name(name: &str) -> &'static str {
+    match name.to_lowercase().as_str() {
+        "beginner" => "Beginner",
+        "easy" => "Easy",
+        "medium" | "normal" => "Medium",
+        "hard" => "Hard",
+        "challenge" | "expert" => "Challenge",
+        _ => "Edit",
+    }
+}
+
+/// Removes the character that ends a tag from a value
+fn escape(value: &str) -> String {
+    value.replace(';', "")
+}
+
+/// Writes notes as measures, snapped to the grid and with as few rows as they need
+fn write_notes(arrows: &[ArrowTimeToml], grid: &BeatGrid) -> String {
+    let measure_length = 4 * ROWS_PER_BEAT;
+    let mut rows: Vec<[char; 4]> = vec![];
+    let row_at = |time: f64| (grid.beat_at(time) * ROWS_PER_BEAT as f64).round().max(0.) as usize;
+    // Writes a note on the first row from `row` where its lane is free, and returns that row
+    let mut place = |row: usize, lane: usize, note: char| {
+        let mut row = row;
+        loop {
+            if row >= rows.len() {
+                let measures = row / measure_length + 1;
+                rows.resize(measures * measure_length, ['0'; 4]);
+            }
+            if rows[row][lane] == '0' {
+                rows[row][lane] = note;
+                return row;
+            }
+            row += 1;
+        }
+    };
+
+    // Placed in order, so notes that snap onto a taken row move after the note that took it
+    let mut arrows: Vec<&ArrowTimeToml> = arrows.iter().collect();
+    arrows.sort_by(|a, b| a.click_time.total_cmp(&b.click_time));
+    for arrow in arrows {
+        let lane = LANES
+            .iter()
+            .position(|direction| *direction == arrow.direction)
+            .unwrap_or(0);
+        // Holds start with a `2` and end with a `3` on a later row
+        let row = row_at(arrow.click_time);
+        match arrow.hold_end {
+            Some(hold_end) => {
+                let row = place(row, lane, '2');
+                place(row_at(hold_end).max(row + 1), lane, '3');
+            }
+            None => {
+                place(row, lane, '1');
+            }
+        }
+    }
+    if rows.is_empty() {
+        rows.resize(measure_length, ['0'; 4]);
+    }
+
+    let measures: Vec<String> = rows
+        .chunks(measure_length)
+        .map(|measure| {
+            // The fewest rows that still have every note on one of them
+            let count = MEASURE_ROWS
+                .iter()
+                .copied()
+                .find(|count| {
+                    let step = measure_length / count;
+                    measure
+                        .iter()
+                        .enumerate()
+                        .all(|(row, lanes)| row % step == 0 || lanes == &['0'; 4])
+                })
+                .unwrap_or(measure_length);
+            measure
+                .iter()
+                .step_by(measure_length / count)
+                .map(|lanes| lanes.iter().collect::<String>() + "\n")
+                .collect()
+        })
+        .collect();
+    measures.join(",\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::test_charts::chart_toml;
+    use crate::types::Speed;
+
+    fn arrow(grid: &BeatGrid, beat: f64, direction: Directions) -> ArrowTimeToml {
+        ArrowTimeToml {
+            click_time: grid.time_at(beat),
+            speed: Speed::Slow,
+            direction,
+            hold_end: None,
+        }
+    }
+
+    /// Times, lanes and hold ends of the notes, in the same order for both charts
+    fn notes(chart: &SongConfigToml) -> Vec<(f64, usize, Option<f64>)> {
+        let mut notes: Vec<(f64, usize, Option<f64>)> = chart
+            .arrows
+            .iter()
+            .map(|arrow| (arrow.click_time, arrow.direction as usize, arrow.hold_end))
+            .collect();
+        notes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        notes
+    }
+
+    fn assert_same_notes(imported: &SongConfigToml, original: &SongConfigToml) {
+        let (imported, original) = (notes(imported), notes(original));
+        assert_eq!(imported.len(), original.len());
+        for (imported, original) in imported.iter().zip(original.iter()) {
+            assert_eq!(imported.1, original.1);
+            assert_eq!(imported.2.is_some(), original.2.is_some());
+            if let (Some(imported_end), Some(original_end)) = (imported.2, original.2) {
+                assert!(
+                    (imported_end - original_end).abs() < 1e-6,
+                    "Hold ending at {} was imported ending at {}",
+                    original_end,
+                    imported_end
+                );
+            }
+            assert!(
+                (imported.0 - original.0).abs() < 1e-6,
+                "Note at {} was imported at {}",
+                original.0,
+                imported.0
+            );
+        }
+    }
+
+    #[test]
+    fn exported_charts_import_the_same() {
+        let grid = BeatGrid {
+            bpm: 150.,
+            offset: 0.3,
+        };
+        let hard = SongConfigToml {
+            format_version: CHART_FORMAT_VERSION,
+            name: "Round trip".to_string(),
+            artist: Some("Someone".to_string()),
+            difficulty: Some("Hard".to_string()),
+            level: Some(7),
+            filename: "song.ogg".to_string(),
+            bpm: Some(grid.bpm),
+            offset: Some(grid.offset),
+            preview_start: Some(10.),
+            // A note before the first beat, quarters, a chord, sixteenths, triplets and a hold
+            arrows: vec![
+                arrow(&grid, -1., Directions::Up),
+                arrow(&grid, 0., Directions::Left),
+                ArrowTimeToml {
+                    hold_end: Some(grid.time_at(6.5)),
+                    ..arrow(&grid, 5., Directions::Right)
+                },
+                arrow(&grid, 1., Directions::Down),
+                arrow(&grid, 1., Directions::Right),
+                arrow(&grid, 2.25, Directions::Up),
+                arrow(&grid, 2.5, Directions::Up),
+                arrow(&grid, 4. + 1. / 3., Directions::Left),
+                arrow(&grid, 4. + 2. / 3., Directions::Down),
+                arrow(&grid, 13., Directions::Right),
+            ],
+        };
+        let easy = SongConfigToml {
+            difficulty: Some("Easy".to_string()),
+            level: Some(2),
+            arrows: vec![arrow(&grid, 0., Directions::Up)],
+            ..hard.clone()
+        };
+        let charts = vec![hard, easy];
+
+        let simfile = Simfile::parse(&export(&charts, grid)).expect("Couldn't parse the export");
+        for original in charts.iter() {
+            let difficulty = original.difficulty.as_deref().unwrap_or("");
+            let imported = simfile
+                .import(difficulty)
+                .expect("Couldn't import the export");
+            assert_eq!(imported.name, original.name);
+            assert_eq!(imported.artist, original.artist);
+            assert_eq!(imported.filename, original.filename);
+            assert_eq!(imported.level, original.level);
+            assert_eq!(imported.bpm, original.bpm);
+            assert_eq!(imported.preview_start, original.preview_start);
+            assert_same_notes(&imported, original);
+        }
+    }
+
+    /// A simfile with one chart of the given notes, at 120 BPM
+    fn simfile(notes: &str) -> Simfile {
+        let text = format!(
+            "#TITLE:Notes;\n#MUSIC:song.ogg;\n#OFFSET:0;\n#BPMS:0=120;\n\
+             #NOTES:dance-single::Hard:5:0,0,0,0,0:\n{};\n",
+            notes
+        );
+        Simfile::parse(&text).expect("Couldn't parse simfile")
+    }
+
+    #[test]
+    fn beats_are_timed_with_bpm_changes_and_stops() {
+        let timing = Timing {
+            offset: -0.5,
+            bpms: vec![(0., 120.), (4., 60.)],
+            stops: vec![(6., 1.)],
+        };
+        assert_eq!(timing.time_at(0.), 0.5);
+        assert_eq!(timing.time_at(2.), 1.5);
+        assert_eq!(timing.time_at(4.), 2.5);
+        assert_eq!(timing.time_at(5.), 3.5);
+        // Notes on a stop are hit before it, and the ones after it are delayed
+        assert_eq!(timing.time_at(6.), 4.5);
+        assert_eq!(timing.time_at(7.), 6.5);
+    }
+
+    #[test]
+    fn ssc_charts_can_have_their_own_timing() {
+        let text = "#TITLE:Timing;\n#MUSIC:song.ogg;\n#OFFSET:0;\n#BPMS:0=120;\n\
+            #NOTEDATA:;\n#STEPSTYPE:dance-single;\n#DIFFICULTY:Easy;\n\
+            #NOTES:\n0000\n,\n1000\n;\n\
+            #NOTEDATA:;\n#STEPSTYPE:dance-single;\n#DIFFICULTY:Hard;\n#BPMS:0=60;\n\
+            #NOTES:\n0000\n,\n1000\n;\n";
+        let simfile = Simfile::parse(text).expect("Couldn't parse simfile");
+        let easy = simfile.import("Easy").expect("Couldn't import Easy");
+        let hard = simfile.import("Hard").expect("Couldn't import Hard");
+        assert_eq!(easy.bpm, Some(120.));
+        assert_eq!(easy.arrows[0].click_time, 2.);
+        assert_eq!(hard.bpm, Some(60.));
+        assert_eq!(hard.arrows[0].click_time, 4.);
+    }
+
+    #[test]
+    fn holds_and_rolls_end_at_their_tail() {
+        let chart = simfile("2004\n0100\n3000\n0003\n")
+            .import("Hard")
+            .expect("Couldn't import simfile");
+        let notes: Vec<(f64, Directions, Option<f64>)> = chart
+            .arrows
+            .iter()
+            .map(|arrow| (arrow.click_time, arrow.direction, arrow.hold_end))
+            .collect();
+        assert_eq!(
+            notes,
+            vec![
+                (0., Directions::Left, Some(1.)),
+                (0., Directions::Right, Some(1.5)),
+                (0.5, Directions::Down, None),
+            ]
+        );
+
+        assert!(simfile("2000\n0000\n0000\n0000\n").import("Hard").is_err());
+        assert!(simfile("3000\n0000\n0000\n0000\n").import("Hard").is_err());
+    }
+
+    #[test]
+    fn mines_are_left_out() {
+        let chart = simfile("1000\n0M00\n00M1\n0000\n")
+            .import("Hard")
+            .expect("Couldn't import simfile");
+        let notes: Vec<(f64, Directions)> = chart
+            .arrows
+            .iter()
+            .map(|arrow| (arrow.click_time, arrow.direction))
+            .collect();
+        assert_eq!(notes, vec![(0., Directions::Left), (1., Directions::Right)]);
+    }
+
+    #[test]
+    fn holds_can_not_start_while_one_is_held() {
+        let error = simfile("2000\n0000\n4000\n3000\n,\n3000\n").import("Hard");
+        assert_eq!(
+            error.unwrap_err(),
+            "Measure 1 starts a hold while one is held"
+        );
+    }
+
+    #[test]
+    fn notes_snapped_onto_the_same_row_move_to_the_next_one() {
+        let grid = BeatGrid {
+            bpm: 120.,
+            offset: 0.,
+        };
+        let row = grid.beat_duration() / ROWS_PER_BEAT as f64;
+        let hold = |beat, end| ArrowTimeToml {
+            hold_end: Some(grid.time_at(end)),
+            ..arrow(&grid, beat, Directions::Up)
+        };
+        // A tap and the start of a hold on one row, and the end of that hold on the row of a tap
+        let mut arrows = vec![
+            arrow(&grid, 1., Directions::Up),
+            hold(1., 2.),
+            arrow(&grid, 2., Directions::Up),
+        ];
+        arrows[1].click_time += row / 4.;
+        let chart = SongConfigToml {
+            bpm: Some(grid.bpm),
+            offset: Some(grid.offset),
+            ..chart_toml(arrows)
+        };
+
+        let simfile = Simfile::parse(&export(&[chart], grid)).expect("Couldn't parse the export");
+        let imported = simfile.import("Edit").expect("Couldn't import the export");
+        let notes: Vec<(f64, Option<f64>)> = imported
+            .arrows
+            .iter()
+            .map(|arrow| (arrow.click_time, arrow.hold_end))
+            .collect();
+        assert_eq!(
+            notes,
+            vec![
+                (grid.time_at(1.), None),
+                (grid.time_at(1.) + row, Some(grid.time_at(2.))),
+                (grid.time_at(2.) + row, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn exported_notes_are_snapped_to_the_grid() {
+        let grid = BeatGrid {
+            bpm: 120.,
+            offset: 0.,
+        };
+        let mut chart = SongConfigToml {
+            bpm: Some(grid.bpm),
+            offset: Some(grid.offset),
+            ..chart_toml(vec![arrow(&grid, 0.5, Directions::Up)])
+        };
+        chart.arrows[0].click_time += 0.003;
+
+        let simfile = Simfile::parse(&export(&[chart], grid)).expect("Couldn't parse the export");
+        let imported = simfile.import("Edit").expect("Couldn't import the export");
+        assert_eq!(imported.arrows[0].click_time, grid.time_at(0.5));
+    }
+}
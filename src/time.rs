@@ -10,25 +10,29 @@ pub struct ControlledTime {
     delta_seconds_f64: f64,
     delta_seconds: f32,
     seconds_since_startup: f64,
-    startup: Instant,
+    paused: bool,
+    speed: f64,
 }
 impl Default for ControlledTime {
     fn default() -> Self {
         Self {
             delta: Duration::from_secs(0),
             last_update: None,
-            startup: Instant::now(),
             delta_seconds_f64: 0.0,
             seconds_since_startup: 0.0,
             delta_seconds: 0.0,
+            paused: false,
+            speed: 1.0,
         }
     }
 }
 
 impl ControlledTime {
     pub fn reset_time(&mut self) {
-        self.startup = Instant::now();
+        self.last_update = Some(Instant::now());
         self.seconds_since_startup = 0.0;
+        self.paused = false;
+        self.speed = 1.0;
     }
 
     pub fn update(&mut self) {
@@ -38,28 +42,67 @@ impl ControlledTime {
 
     pub fn update_with_instant(&mut self, instant: Instant) {
         if let Some(last_update) = self.last_update {
-            self.delta = instant - last_update;
-            self.delta_seconds_f64 = self.delta.as_secs_f64();
-            self.delta_seconds = self.delta.as_secs_f32();
+            self.set_delta(instant - last_update);
         }
 
-        let duration_since_startup = instant - self.startup;
-        self.seconds_since_startup = duration_since_startup.as_secs_f64();
+        self.seconds_since_startup += self.delta_seconds_f64;
         self.last_update = Some(instant);
     }
 
+    /// Moves time forward by a fixed step instead of reading the clock, for simulations
+    pub fn advance(&mut self, seconds: f64) {
+        self.set_delta(Duration::from_secs_f64(seconds));
+        self.seconds_since_startup += self.delta_seconds_f64;
+    }
+
+    fn set_delta(&mut self, elapsed: Duration) {
+        self.delta = if self.paused {
+            Duration::from_secs(0)
+        } else {
+            elapsed.mul_f64(self.speed)
+        };
+        self.delta_seconds_f64 = self.delta.as_secs_f64();
+        self.delta_seconds = self.delta.as_secs_f32();
+    }
+
+    /// Makes the next update ignore the time that passed since the last one
+    pub fn discard_elapsed(&mut self) {
+        self.last_update = Some(Instant::now());
+    }
+
+    /// Stops time from advancing
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Changes how fast time advances, 1 being real time
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Jumps to a time since startup in seconds
+    pub fn seek(&mut self, seconds: f64) {
+        self.seconds_since_startup = seconds;
+    }
+
     /// The delta between the current and last tick as [`f32`] seconds
     #[inline]
     pub fn delta_seconds(&self) -> f32 {
         self.delta_seconds
     }
 
-    /// The delta between the current and last tick as [`f64`] seconds
-    #[inline]
-    pub fn delta_seconds_f64(&self) -> f64 {
-        self.delta_seconds_f64
-    }
-
     /// The time since startup in seconds
     #[inline]
     pub fn seconds_since_startup(&self) -> f64 {
@@ -88,8 +131,15 @@ impl Plugin for TimePlugin {
                     .with_system(reset_time_when_entering_game.system()),
             )
             .add_system_set(SystemSet::on_update(AppState::Game).with_system(update_time.system()))
+            .add_system_set(
+                SystemSet::on_enter(AppState::EditMap)
+                    .with_system(reset_time_when_entering_game.system()),
+            )
             .add_system_set(
                 SystemSet::on_update(AppState::MakeMap).with_system(update_time.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::EditMap).with_system(update_time.system()),
             );
     }
 }
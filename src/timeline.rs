@@ -0,0 +1,238 @@
+use crate::config::RhythmConfig;
+use crate::consts::*;
+use crate::types::{
+    ArrowTimeToml, BeatGrid,
+    Directions::{self, *},
+};
+use bevy::{
+    input::{keyboard::KeyCode, mouse::MouseWheel, Input},
+    prelude::*,
+};
+
+/// Visible part of the timeline used by the chart editor and the map maker
+pub struct TimelineView {
+    /// Time at the left edge of the timeline
+    pub start: f64,
+    /// Pixels per second
+    pub zoom: f32,
+}
+impl Default for TimelineView {
+    fn default() -> Self {
+        Self {
+            start: 0.,
+            zoom: TIMELINE_ZOOM,
+        }
+    }
+}
+impl TimelineView {
+    /// Returns the x coordinate for a time
+    pub fn x(&self, time: f64) -> f32 {
+        TIMELINE_START + ((time - self.start) as f32) * self.zoom
+    }
+
+    /// Returns the time at an x coordinate
+    pub fn time_at(&self, x: f32) -> f64 {
+        self.start + ((x - TIMELINE_START) / self.zoom) as f64
+    }
+
+    /// Returns the time at an x coordinate, rounded to hundredths of a second
+    pub fn time(&self, x: f32) -> f64 {
+        (self.time_at(x).max(0.) * 100.).round() / 100.
+    }
+
+    /// Returns the time at the right edge of the window
+    pub fn end(&self) -> f64 {
+        self.time_at(400.)
+    }
+
+    /// Returns the index of the note under a position
+    pub fn note_at(&self, arrows: &[ArrowTimeToml], position: Vec2) -> Option<usize> {
+        arrows.iter().rposition(|arrow| {
+            let center = Vec2::new(self.x(arrow.click_time), arrow.direction.y());
+            center.distance(position) <= TIMELINE_NOTE_SIZE / 2.
+        })
+    }
+
+    /// Checks if a note at this time would be on screen
+    pub fn is_visible(&self, time: f64) -> bool {
+        let x = self.x(time);
+        (-400. - TIMELINE_NOTE_SIZE..=400. + TIMELINE_NOTE_SIZE).contains(&x)
+    }
+}
+
+/// Returns the lane under a y coordinate
+pub fn lane_at(y: f32) -> Option<Directions> {
+    let directions = [Up, Down, Left, Right];
+    directions
+        .iter()
+        .find(|direction| (direction.y() - y).abs() <= 50.)
+        .copied()
+}
+
+/// Returns the cursor position in world coordinates
+pub fn cursor_position(windows: &Windows) -> Option<Vec2> {
+    let window = windows.get_primary()?;
+    let position = window.cursor_position()?;
+    Some(position - Vec2::new(window.width(), window.height()) / 2.)
+}
+
+pub fn reset_timeline(mut view: ResMut<TimelineView>) {
+    *view = TimelineView::default();
+}
+
+/// Scrolls the timeline with the mouse wheel, or zooms it while holding Control
+pub fn scroll_timeline(
+    mut wheel_events: EventReader<MouseWheel>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut view: ResMut<TimelineView>,
+) {
+    let control =
+        keyboard_input.pressed(KeyCode::LControl) || keyboard_input.pressed(KeyCode::RControl);
+
+    for event in wheel_events.iter() {
+        if control {
+            view.zoom = (view.zoom * 1.1_f32.powf(event.y)).clamp(20., 1000.);
+        } else {
+            view.start = (view.start - (event.y * 100. / view.zoom) as f64).max(0.);
+        }
+    }
+}
+
+/// Keeps the materials used to draw the timeline
+pub struct TimelineMaterials {
+    line: Handle<ColorMaterial>,
+    second_line: Handle<ColorMaterial>,
+    beat_line: Handle<ColorMaterial>,
+    pub playhead: Handle<ColorMaterial>,
+    pub loop_marker: Handle<ColorMaterial>,
+    pub font: Handle<Font>,
+}
+impl FromWorld for TimelineMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let world = world.cell();
+
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        let asset_server = world.get_resource::<AssetServer>().unwrap();
+        let config = world.get_resource::<RhythmConfig>().unwrap();
+        TimelineMaterials {
+            line: materials.add(Color::rgb(0.3, 0.3, 0.3).into()),
+            second_line: materials.add(Color::rgb(0.5, 0.5, 0.5).into()),
+            beat_line: materials.add(Color::rgb(0.35, 0.45, 0.6).into()),
+            playhead: materials.add(Color::rgb(0.9, 0.3, 0.3).into()),
+            loop_marker: materials.add(Color::rgb(0.9, 0.8, 0.3).into()),
+            font: asset_server.load(config.assets.font.as_str()),
+        }
+    }
+}
+
+/// Marks everything that gets redrawn when the timeline changes
+pub struct TimelineSprite;
+
+/// Despawns everything drawn on the timeline
+pub fn clear_timeline(commands: &mut Commands, query: &Query<Entity, With<TimelineSprite>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Draws the lanes, and a marker on every beat of `beat_grid`, or on every second without one
+pub fn spawn_timeline_grid(
+    commands: &mut Commands,
+    view: &TimelineView,
+    materials: &TimelineMaterials,
+    beat_grid: Option<BeatGrid>,
+) {
+    // Lanes
+    let directions = [Up, Down, Left, Right];
+    for direction in directions.iter() {
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: materials.line.clone(),
+                sprite: Sprite::new(Vec2::new(800., 2.)),
+                transform: Transform::from_translation(Vec3::new(0., direction.y(), 0.1)),
+                ..Default::default()
+            })
+            .insert(TimelineSprite);
+    }
+
+    // Beat or second markers
+    let (material, mut time, step) = match beat_grid {
+        Some(grid) => (
+            materials.beat_line.clone(),
+            grid.time_at(grid.beat_at(view.start).ceil()),
+            grid.beat_duration(),
+        ),
+        None => (materials.second_line.clone(), view.start.ceil(), 1.),
+    };
+    while time <= view.end() {
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: material.clone(),
+                sprite: Sprite::new(Vec2::new(2., 400.)),
+                transform: Transform::from_translation(Vec3::new(view.x(time), 0., 0.2)),
+                ..Default::default()
+            })
+            .insert(TimelineSprite);
+        time += step;
+    }
+}
+
+/// Draws a note on the timeline
+pub fn spawn_timeline_note(
+    commands: &mut Commands,
+    view: &TimelineView,
+    arrow: &ArrowTimeToml,
+    material: Handle<ColorMaterial>,
+    size: f32,
+    z: f32,
+) {
+    let mut transform =
+        Transform::from_translation(Vec3::new(view.x(arrow.click_time), arrow.direction.y(), z));
+    transform.rotate(Quat::from_rotation_z(arrow.direction.rotation()));
+    commands
+        .spawn_bundle(SpriteBundle {
+            material,
+            sprite: Sprite::new(Vec2::splat(size)),
+            transform,
+            ..Default::default()
+        })
+        .insert(TimelineSprite);
+}
+
+/// Draws the tail of a hold note on the timeline, from its click time to its end
+pub fn spawn_timeline_hold(
+    commands: &mut Commands,
+    view: &TimelineView,
+    arrow: &ArrowTimeToml,
+    material: Handle<ColorMaterial>,
+) {
+    let hold_end = match arrow.hold_end {
+        Some(hold_end) => hold_end,
+        None => return,
+    };
+    let (start, end) = (view.x(arrow.click_time), view.x(hold_end));
+    commands
+        .spawn_bundle(SpriteBundle {
+            material,
+            sprite: Sprite::new(Vec2::new(end - start, TIMELINE_NOTE_SIZE / 2.)),
+            transform: Transform::from_translation(Vec3::new(
+                (start + end) / 2.,
+                arrow.direction.y(),
+                0.9,
+            )),
+            visible: Visible {
+                is_transparent: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(TimelineSprite);
+}
+
+pub struct TimelinePlugin;
+impl Plugin for TimelinePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<TimelineView>()
+            .init_resource::<TimelineMaterials>();
+    }
+}
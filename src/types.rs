@@ -1,11 +1,13 @@
-use crate::consts::*;
-use bevy::input::{keyboard::KeyCode, Input};
-use bevy::prelude::*;
+use crate::chart_format;
+use crate::config::Layout;
+use crate::import;
+use crate::library;
 use core::f32::consts::PI;
 
 use serde_derive::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::Path;
 
 #[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum Directions {
@@ -15,29 +17,12 @@ pub enum Directions {
     Right,
 }
 impl Directions {
-    /// Checks if a key that corresponds to this direction has been pressed
-    pub fn key_just_pressed(&self, input: &Input<KeyCode>) -> bool {
-        let keys = match self {
-            Directions::Up => [KeyCode::Up, KeyCode::D],
-            Directions::Down => [KeyCode::Down, KeyCode::F],
-            Directions::Left => [KeyCode::Left, KeyCode::J],
-            Directions::Right => [KeyCode::Right, KeyCode::K],
-        };
-
-        keys.iter().any(|code| input.just_pressed(*code))
-    }
-
-    /// Checks if a key that corresponds to this direction is being pressed
-    pub fn key_pressed(&self, input: &Input<KeyCode>) -> bool {
-        let keys = match self {
-            Directions::Up => [KeyCode::Up, KeyCode::D],
-            Directions::Down => [KeyCode::Down, KeyCode::F],
-            Directions::Left => [KeyCode::Left, KeyCode::J],
-            Directions::Right => [KeyCode::Right, KeyCode::K],
-        };
-
-        keys.iter().any(|code| input.pressed(*code))
-    }
+    pub const ALL: [Directions; 4] = [
+        Directions::Up,
+        Directions::Down,
+        Directions::Left,
+        Directions::Right,
+    ];
 
     /// Returns the correct rotation for an arrow with this direction
     pub fn rotation(&self) -> f32 {
@@ -49,7 +34,7 @@ impl Directions {
         }
     }
 
-    /// Returns the correct y coordinate for an arrow with this direction
+    /// Returns the y coordinate of this direction's lane in the editors and the default layout
     pub fn y(&self) -> f32 {
         match self {
             Directions::Up => 150.,
@@ -60,17 +45,13 @@ impl Directions {
     }
 }
 
-#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum Speed {
     Slow,
     Medium,
     Fast,
 }
 impl Speed {
-    /// Returns actual speed at which the arrow should move
-    pub fn value(&self) -> f32 {
-        BASE_SPEED * self.multiplier()
-    }
     /// Speed multiplier
     pub fn multiplier(&self) -> f32 {
         match self {
@@ -81,70 +62,312 @@ impl Speed {
     }
 }
 
+/// Tempo of a song, used to place notes on beats
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BeatGrid {
+    pub bpm: f64,
+    /// Time of the first beat, in seconds
+    pub offset: f64,
+}
+impl BeatGrid {
+    /// Duration of a beat in seconds
+    pub fn beat_duration(&self) -> f64 {
+        60. / self.bpm
+    }
+
+    /// Returns the beat at a time, with the fractional part being how far into the beat it is
+    pub fn beat_at(&self, time: f64) -> f64 {
+        (time - self.offset) / self.beat_duration()
+    }
+
+    /// Returns the time at which a beat happens
+    pub fn time_at(&self, beat: f64) -> f64 {
+        self.offset + beat * self.beat_duration()
+    }
+
+    /// Moves a time to the closest subdivision of a beat
+    pub fn snap(&self, time: f64, subdivision: Subdivision) -> f64 {
+        let steps = subdivision.per_beat() as f64;
+        self.time_at((self.beat_at(time) * steps).round() / steps)
+    }
+}
+
+/// Note lengths that recorded notes can be snapped to
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Subdivision {
+    Quarter,
+    Eighth,
+    Sixteenth,
+    EighthTriplet,
+    SixteenthTriplet,
+}
+impl Subdivision {
+    /// Number of notes of this length in a beat
+    pub fn per_beat(&self) -> u32 {
+        match self {
+            Subdivision::Quarter => 1,
+            Subdivision::Eighth => 2,
+            Subdivision::Sixteenth => 4,
+            Subdivision::EighthTriplet => 3,
+            Subdivision::SixteenthTriplet => 6,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Subdivision::Quarter => "1/4",
+            Subdivision::Eighth => "1/8",
+            Subdivision::Sixteenth => "1/16",
+            Subdivision::EighthTriplet => "1/12",
+            Subdivision::SixteenthTriplet => "1/24",
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
-/// Keeps track of when each arrow should spawn and it's speed and direction
+/// Keeps track of when each arrow should be clicked and it's speed and direction
 pub struct ArrowTime {
-    pub spawn_time: f64,
+    pub click_time: f64,
     pub speed: Speed,
     pub direction: Directions,
+    /// Time the lane can be released, for hold notes
+    pub hold_end: Option<f64>,
 }
 impl ArrowTime {
     fn new(arrow: &ArrowTimeToml) -> Self {
-        let speed_value = arrow.speed.value();
         Self {
-            spawn_time: arrow.click_time - (DISTANCE / speed_value) as f64,
+            click_time: arrow.click_time,
             speed: arrow.speed,
             direction: arrow.direction,
+            hold_end: arrow.hold_end,
         }
     }
+
+    /// Returns when the arrow should spawn to reach the target on its click time
+    pub fn spawn_time(&self, layout: &Layout) -> f64 {
+        self.click_time - layout.travel_time(self.speed)
+    }
 }
 
 pub struct SongConfig {
     pub name: String,
-    pub song_audio: Handle<AudioSource>,
+    /// Path of the chart in `assets/songs` without its extension, like `test` for `test.toml`
+    pub chart: String,
+    /// Hash of the notes, so scores of older versions of the chart are kept apart
+    pub chart_hash: String,
+    /// Path of the song's audio in `assets/songs`
+    pub song_audio: String,
     pub arrows: Vec<ArrowTime>,
+    pub beat_grid: Option<BeatGrid>,
+    /// Times when each note should be hit, sorted
+    pub click_times: Vec<f64>,
+    /// Time the last note is done, which is later than its click time if it's a hold
+    pub end_time: f64,
 }
 
-pub fn load_config(path: &str, asset_server: &AssetServer) -> SongConfig {
-    // Open file and read contents
-    let mut file = File::open(format!("assets/songs/{}", path)).expect("Couldn't open file");
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)
-        .expect("Couldn't read file into String");
+/// Reads and parses a chart file from `assets/songs`, which can be inside a zipped pack
+pub fn read_config(path: &str) -> SongConfigToml {
+    parse_config(path).expect("Couldn't parse into SongConfigToml")
+}
 
-    // Parse using toml and Serde
-    let parsed: SongConfigToml =
-        toml::from_str(&contents).expect("Couldn't parse into SongConfigToml");
+/// Same as `read_config`, returning what went wrong instead of panicking
+pub fn parse_config(path: &str) -> Result<SongConfigToml, String> {
+    let bytes = match library::read_file(path) {
+        Ok(bytes) => bytes,
+        // Charts of other games are converted when read, unless they were saved as toml
+        Err(error) => {
+            return import::import_chart(path.trim_end_matches(".toml"))
+                .unwrap_or_else(|| Err(error.to_string()))
+        }
+    };
+    let contents = String::from_utf8(bytes).map_err(|error| error.to_string())?;
 
-    // Process arrows
-    let mut arrows = parsed
-        .arrows
-        .iter()
-        .map(|arr| ArrowTime::new(arr))
-        .collect::<Vec<ArrowTime>>();
-    // Sort arrows by spawn_time
-    arrows.sort_by(|a, b| a.spawn_time.partial_cmp(&b.spawn_time).unwrap());
+    // Parse using toml and Serde, updating charts of older versions
+    chart_format::parse(&contents).map(|(config, _)| config)
+}
 
-    // Load song audio
-    let song_audio = asset_server.load(&*format!("songs/{}", parsed.filename));
+/// Hashes bytes with FNV-1a, which stays the same between runs and versions
+pub fn fnv_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+/// Turns a song name into a file stem, replacing anything that isn't a letter or number
+pub fn file_stem(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Returns a stem for a new chart file in `assets/songs` that isn't taken, numbering it if it is
+pub fn unused_chart_stem(stem: &str) -> String {
+    let taken = |stem: &str| Path::new(&format!("assets/songs/{}.toml", stem)).exists();
+    if !taken(stem) {
+        return stem.to_string();
+    }
+    (2..)
+        .map(|number| format!("{}_{}", stem, number))
+        .find(|stem| !taken(stem))
+        .expect("Couldn't find an unused chart name")
+}
+
+/// Writes a chart file into `assets/songs`
+pub fn write_config(path: &str, config: &SongConfigToml) {
+    let text = toml::to_string(config).expect("Couldn't convert to toml text");
+
+    let mut file = File::create(format!("assets/songs/{}", path)).expect("Couldn't create file");
+    file.write_all(text.as_bytes())
+        .expect("Couldn't write String into file");
+}
+
+pub fn load_config(path: &str) -> SongConfig {
+    SongConfig::new(path.trim_end_matches(".toml"), read_config(path))
+}
+
+impl SongConfig {
+    /// Prepares a parsed chart to be played, `chart` being its path without extension
+    pub fn new(chart: &str, parsed: SongConfigToml) -> Self {
+        // Process arrows
+        let mut arrows = parsed
+            .arrows
+            .iter()
+            .map(|arr| ArrowTime::new(arr))
+            .collect::<Vec<ArrowTime>>();
+        // Sort arrows by click_time
+        arrows.sort_by(|a, b| a.click_time.total_cmp(&b.click_time));
 
-    SongConfig {
-        name: parsed.name,
-        song_audio,
-        arrows,
+        let mut click_times: Vec<f64> = parsed.arrows.iter().map(|arr| arr.click_time).collect();
+        click_times.sort_by(|a, b| a.total_cmp(b));
+
+        SongConfig {
+            chart: chart.to_string(),
+            chart_hash: parsed.chart_hash(),
+            end_time: parsed.length(),
+            beat_grid: parsed.beat_grid(),
+            song_audio: library::resolve(chart, &parsed.filename),
+            name: parsed.name,
+            arrows,
+            click_times,
+        }
     }
 }
 
-#[derive(Deserialize, Debug)]
-struct SongConfigToml {
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SongConfigToml {
+    /// Version of the chart format the file was written in, see `chart_format`
+    pub format_version: u32,
     pub name: String,
+    pub artist: Option<String>,
+    /// Name of the difficulty, like "Hard"
+    pub difficulty: Option<String>,
+    /// Difficulty rating, higher is harder
+    pub level: Option<u32>,
     pub filename: String,
+    /// Beats per minute of the song, if known
+    pub bpm: Option<f64>,
+    /// Time of the first beat, in seconds
+    pub offset: Option<f64>,
+    /// Time the menu preview starts at, in seconds. Defaults to the first note
+    pub preview_start: Option<f64>,
     pub arrows: Vec<ArrowTimeToml>,
 }
+impl SongConfigToml {
+    /// Returns the time the menu preview starts at
+    pub fn preview_start(&self) -> f64 {
+        self.preview_start.unwrap_or_else(|| {
+            let first_note = self
+                .arrows
+                .iter()
+                .map(|arrow| arrow.click_time)
+                .reduce(f64::min);
+            first_note.unwrap_or(0.).max(0.)
+        })
+    }
+
+    /// Returns the time the last note is done, in seconds
+    pub fn length(&self) -> f64 {
+        self.arrows
+            .iter()
+            .map(|arrow| arrow.hold_end.unwrap_or(arrow.click_time))
+            .fold(0., f64::max)
+    }
+
+    /// Returns a hash of the notes, which changes when they're edited but not with the metadata
+    pub fn chart_hash(&self) -> String {
+        let notes: String = self
+            .arrows
+            .iter()
+            .map(|arrow| {
+                // Taps are hashed like before there were holds, so their scores are kept
+                let hold = match arrow.hold_end {
+                    Some(hold_end) => format!(" {:.3}", hold_end),
+                    None => String::new(),
+                };
+                format!(
+                    "{:.3} {:?} {:?}{}\n",
+                    arrow.click_time, arrow.speed, arrow.direction, hold
+                )
+            })
+            .collect();
+        format!("{:016x}", fnv_hash(notes.as_bytes()))
+    }
+
+    /// Returns the beat grid of the song, if it has a BPM
+    pub fn beat_grid(&self) -> Option<BeatGrid> {
+        self.bpm.map(|bpm| BeatGrid {
+            bpm,
+            offset: self.offset.unwrap_or(0.),
+        })
+    }
+}
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct ArrowTimeToml {
     pub click_time: f64,
     pub speed: Speed,
     pub direction: Directions,
+    /// Time the lane can be released, for hold notes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hold_end: Option<f64>,
+}
+
+/// Charts for the tests of other modules
+#[cfg(test)]
+pub mod test_charts {
+    use super::*;
+    use crate::consts::CHART_FORMAT_VERSION;
+
+    /// A note moving at medium speed, a hold if it has an end
+    pub fn note(click_time: f64, direction: Directions, hold_end: Option<f64>) -> ArrowTimeToml {
+        ArrowTimeToml {
+            click_time,
+            speed: Speed::Medium,
+            direction,
+            hold_end,
+        }
+    }
+
+    /// A chart file with the notes and only the fields it needs
+    pub fn chart_toml(arrows: Vec<ArrowTimeToml>) -> SongConfigToml {
+        SongConfigToml {
+            format_version: CHART_FORMAT_VERSION,
+            name: "Test".to_string(),
+            artist: None,
+            difficulty: None,
+            level: None,
+            filename: "test.mp3".to_string(),
+            bpm: None,
+            offset: None,
+            preview_start: None,
+            arrows,
+        }
+    }
+
+    /// A chart with the notes, parsed the same way as when playing
+    pub fn chart(arrows: Vec<ArrowTimeToml>) -> SongConfig {
+        SongConfig::new("test", chart_toml(arrows))
+    }
 }
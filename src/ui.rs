@@ -1,14 +1,16 @@
+use crate::config::RhythmConfig;
 use crate::consts::*;
+use crate::score::ScoreResource;
 use crate::time::ControlledTime;
-use crate::ScoreResource;
 use bevy::prelude::*;
 
 fn setup_ui(
     mut commands: Commands,
     asset_server: ResMut<AssetServer>,
     mut color_materials: ResMut<Assets<ColorMaterial>>,
+    config: Res<RhythmConfig>,
 ) {
-    let font: Handle<Font> = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let font: Handle<Font> = asset_server.load(config.assets.font.as_str());
     let material = color_materials.add(Color::NONE.into());
 
     commands
@@ -26,6 +28,7 @@ fn setup_ui(
             material: material.clone(),
             ..Default::default()
         })
+        .insert(GameUI)
         .with_children(|parent| {
             parent
                 .spawn_bundle(TextBundle {
@@ -56,6 +59,7 @@ fn setup_ui(
             material,
             ..Default::default()
         })
+        .insert(GameUI)
         .with_children(|parent| {
             parent
                 .spawn_bundle(TextBundle {
@@ -74,11 +78,20 @@ fn setup_ui(
         });
 }
 
+/// Root of the nodes shown while playing a song
+struct GameUI;
+
+fn despawn_ui(mut commands: Commands, query: Query<Entity, With<GameUI>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
 struct TimeText;
 
 fn update_time_text(time: Res<ControlledTime>, mut query: Query<(&mut Text, &TimeText)>) {
     // Song starts 3 seconds after real time
-    let secs = time.seconds_since_startup() - 3.;
+    let secs = time.seconds_since_startup() - SONG_START_DELAY;
 
     // Don't do anything before the song starts
     if secs < 0. {
@@ -114,6 +127,7 @@ impl Plugin for UIPlugin {
                 SystemSet::on_update(AppState::Game)
                     .with_system(update_time_text.system())
                     .with_system(update_score_text.system()),
-            );
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Game).with_system(despawn_ui.system()));
     }
 }
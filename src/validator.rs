@@ -0,0 +1,157 @@
+use crate::cli::charts_or_all;
+use crate::config::Layout;
+use crate::consts::*;
+use crate::library;
+use crate::playback::DecodedSong;
+use crate::types::{parse_config, ArrowTime, SongConfig};
+use bevy::audio::AudioSource;
+use std::process;
+
+/// Returns the problems of a chart in `assets/songs`, `chart` being its path without extension
+pub fn validate_chart(chart: &str) -> Vec<String> {
+    let parsed = match parse_config(&format!("{}.toml", chart)) {
+        Ok(parsed) => parsed,
+        Err(error) => return vec![format!("Couldn't parse chart: {}", error)],
+    };
+
+    let mut problems = vec![];
+    let audio = library::resolve(chart, &parsed.filename);
+    let duration = audio_duration(&audio);
+    if duration.is_none() {
+        problems.push(format!("Couldn't read audio file {}", audio));
+    }
+
+    // Parsed the same way as when playing, so the notes are sorted by time
+    let config = SongConfig::new(chart, parsed);
+    let layout = Layout::default();
+    for arrow in config.arrows.iter() {
+        // The game's clock starts `SONG_START_DELAY` before the song
+        let spawn_time = arrow.spawn_time(&layout) + SONG_START_DELAY;
+        if spawn_time < 0. {
+            problems.push(format!(
+                "{:?} note at {:.3}s would have to spawn {:.3}s before the game starts",
+                arrow.direction, arrow.click_time, -spawn_time
+            ));
+        }
+        if let Some(duration) = duration {
+            if arrow.click_time > duration {
+                problems.push(format!(
+                    "{:?} note at {:.3}s is after the audio ends at {:.3}s",
+                    arrow.direction, arrow.click_time, duration
+                ));
+            } else if arrow.hold_end.is_some_and(|hold_end| hold_end > duration) {
+                problems.push(format!(
+                    "{:?} hold at {:.3}s ends after the audio ends at {:.3}s",
+                    arrow.direction, arrow.click_time, duration
+                ));
+            }
+        }
+    }
+    problems.extend(overlapping_notes(&config.arrows, &layout));
+
+    problems
+}
+
+/// Returns the length of an audio file in `assets/songs`, without writing the decoded audio to the cache
+fn audio_duration(filename: &str) -> Option<f64> {
+    let bytes = library::read_file(filename).ok()?;
+    DecodedSong::decode_duration(&AudioSource {
+        bytes: bytes.into(),
+    })
+}
+
+/// Finds notes on the same lane that one press would hit together, or that start while a hold is held
+fn overlapping_notes(arrows: &[ArrowTime], layout: &Layout) -> Vec<String> {
+    let mut problems = vec![];
+    for (i, arrow) in arrows.iter().enumerate() {
+        if let Some(hold_end) = arrow.hold_end {
+            // Notes are sorted, so the ones during the hold come first
+            let held = arrows[i + 1..]
+                .iter()
+                .filter(|next| next.direction == arrow.direction)
+                .take_while(|next| next.click_time <= hold_end);
+            for next in held {
+                problems.push(format!(
+                    "{:?} note at {:.3}s starts while the hold from {:.3}s to {:.3}s is held",
+                    arrow.direction, next.click_time, arrow.click_time, hold_end
+                ));
+            }
+            continue;
+        }
+
+        let next = arrows[i + 1..]
+            .iter()
+            .find(|next| next.direction == arrow.direction);
+        let next = match next {
+            Some(next) => next,
+            None => continue,
+        };
+
+        let gap = next.click_time - arrow.click_time;
+        let hit_window = (layout.threshold / layout.speed(arrow.speed)) as f64;
+        if gap < DUPLICATE_MARGIN {
+            problems.push(format!(
+                "{:?} note at {:.3}s is duplicated",
+                arrow.direction, arrow.click_time
+            ));
+        } else if gap < hit_window {
+            problems.push(format!(
+                "{:?} notes at {:.3}s and {:.3}s overlap and can't be pressed separately",
+                arrow.direction, arrow.click_time, next.click_time
+            ));
+        }
+    }
+    problems
+}
+
+const USAGE: &str = "Usage: bevy_rhythm validate [chart...]
+
+Checks charts in `assets/songs` without starting the game, and prints every problem found.
+Without charts, checks every chart in `assets/songs`. Exits with an error if there were problems.";
+
+/// Validates charts from the command line
+pub fn run_cli(args: &[String]) {
+    let charts = charts_or_all(args, USAGE);
+
+    let mut failed = 0;
+    for chart in charts.iter() {
+        let problems = validate_chart(chart);
+        if !problems.is_empty() {
+            failed += 1;
+        }
+        for problem in problems {
+            println!("{}: {}", chart, problem);
+        }
+    }
+
+    println!("{} of {} charts have problems", failed, charts.len());
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::test_charts::{chart, note};
+    use crate::types::Directions;
+
+    #[test]
+    fn notes_during_holds_overlap() {
+        let config = chart(vec![
+            note(1., Directions::Up, Some(3.)),
+            note(1.5, Directions::Down, None),
+            note(2., Directions::Up, None),
+            note(2.5, Directions::Up, Some(4.)),
+            note(5., Directions::Up, None),
+        ]);
+        let problems = overlapping_notes(&config.arrows, &Layout::default());
+        assert_eq!(
+            problems,
+            vec![
+                "Up note at 2.000s starts while the hold from 1.000s to 3.000s is held",
+                "Up note at 2.500s starts while the hold from 1.000s to 3.000s is held",
+            ]
+        );
+    }
+}
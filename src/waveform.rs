@@ -0,0 +1,375 @@
+use crate::consts::*;
+use crate::playback::{cache_path, read_u32, read_u64, DecodedSong, Playback};
+use crate::timeline::TimelineView;
+use bevy::{
+    input::{keyboard::KeyCode, Input},
+    prelude::*,
+    render::texture::{Extent3d, TextureDimension, TextureFormat},
+};
+use std::{f32::consts::PI, fs, io};
+
+/// Number of waveform peaks per second of song
+const PEAKS_PER_SECOND: f64 = 200.;
+/// Number of spectrogram columns per second of song
+const SPECTROGRAM_FRAMES_PER_SECOND: f64 = 20.;
+/// Number of frequency bands in the spectrogram
+const SPECTROGRAM_BANDS: usize = 32;
+/// Number of samples analysed for each spectrogram column
+const SPECTROGRAM_WINDOW: usize = 1024;
+/// Lowest and highest frequencies shown in the spectrogram, in Hz
+const SPECTROGRAM_RANGE: (f32, f32) = (60., 8000.);
+
+/// Size in pixels of the waveform and spectrogram textures
+const WAVEFORM_SIZE: (u32, u32) = (400, 50);
+const SPECTROGRAM_SIZE: (u32, u32) = (400, SPECTROGRAM_BANDS as u32);
+
+/// Waveform and spectrogram of a song
+pub struct SongAnalysis {
+    /// Lowest and highest sample in each slice of `1 / PEAKS_PER_SECOND` seconds
+    peaks: Vec<(f32, f32)>,
+    /// Loudness of each band, from 0 to 255, in each slice of `1 / SPECTROGRAM_FRAMES_PER_SECOND` seconds
+    spectrogram: Vec<[u8; SPECTROGRAM_BANDS]>,
+}
+impl SongAnalysis {
+    pub fn analyze(song: &DecodedSong) -> Self {
+        // Mix all channels into one
+        let channels = song.channels as usize;
+        let mono: Vec<f32> = song
+            .samples
+            .chunks(channels)
+            .map(|frame| frame.iter().map(|s| *s as f32).sum::<f32>() / channels as f32 / 32768.)
+            .collect();
+
+        let sample_rate = song.sample_rate as f64;
+        let peak_len = ((sample_rate / PEAKS_PER_SECOND) as usize).max(1);
+        let peaks = mono
+            .chunks(peak_len)
+            .map(|chunk| {
+                chunk.iter().fold((0f32, 0f32), |(min, max), sample| {
+                    (min.min(*sample), max.max(*sample))
+                })
+            })
+            .collect();
+
+        // Hann window and the Goertzel coefficient for each band
+        let window: Vec<f32> = (0..SPECTROGRAM_WINDOW)
+            .map(|n| 0.5 - 0.5 * (2. * PI * n as f32 / SPECTROGRAM_WINDOW as f32).cos())
+            .collect();
+        let (low, high) = SPECTROGRAM_RANGE;
+        let coefficients: Vec<f32> = (0..SPECTROGRAM_BANDS)
+            .map(|band| {
+                let frequency =
+                    low * (high / low).powf(band as f32 / (SPECTROGRAM_BANDS - 1) as f32);
+                2. * (2. * PI * frequency / song.sample_rate as f32).cos()
+            })
+            .collect();
+
+        let hop = ((sample_rate / SPECTROGRAM_FRAMES_PER_SECOND) as usize).max(1);
+        let spectrogram = (0..mono.len() / hop)
+            .map(|frame| {
+                let start = frame * hop;
+                let end = (start + SPECTROGRAM_WINDOW).min(mono.len());
+                let samples = &mono[start..end];
+
+                let mut bands = [0; SPECTROGRAM_BANDS];
+                for (band, coefficient) in bands.iter_mut().zip(coefficients.iter()) {
+                    let (mut previous, mut before_previous) = (0f32, 0f32);
+                    for (sample, weight) in samples.iter().zip(window.iter()) {
+                        let current = sample * weight + coefficient * previous - before_previous;
+                        before_previous = previous;
+                        previous = current;
+                    }
+                    let power = previous * previous + before_previous * before_previous
+                        - coefficient * previous * before_previous;
+
+                    // Map -80dB..0dB to 0..255
+                    let magnitude = power.max(0.).sqrt() / (SPECTROGRAM_WINDOW as f32 / 4.);
+                    let decibels = 20. * (magnitude + 1e-9).log10();
+                    *band = ((decibels + 80.) / 80. * 255.).clamp(0., 255.) as u8;
+                }
+                bands
+            })
+            .collect();
+
+        Self { peaks, spectrogram }
+    }
+
+    /// Reads the analysis from the cache, or makes it and writes it to the cache
+    pub fn load_cached(filename: &str, song: &DecodedSong) -> Self {
+        let path = cache_path(filename, "analysis");
+        if let Some(analysis) = Self::read_cache(&path, song.source_hash) {
+            return analysis;
+        }
+
+        let analysis = Self::analyze(song);
+        // The cache only makes reopening faster, so failing to write it is fine
+        let _ = analysis.write_cache(&path, song.source_hash);
+        analysis
+    }
+
+    /// Reads a cached analysis, if it was made from the same audio file
+    fn read_cache(path: &str, source_hash: u64) -> Option<Self> {
+        let bytes = fs::read(path).ok()?;
+        if bytes.len() < 12 || read_u64(&bytes[0..8]) != source_hash {
+            return None;
+        }
+
+        let peaks_len = read_u32(&bytes[8..12]) as usize;
+        let spectrogram_start = 12 + peaks_len * 8;
+        if bytes.len() < spectrogram_start {
+            return None;
+        }
+        let peaks = bytes[12..spectrogram_start]
+            .chunks_exact(8)
+            .map(|chunk| {
+                let min = f32::from_bits(read_u32(&chunk[0..4]));
+                let max = f32::from_bits(read_u32(&chunk[4..8]));
+                (min, max)
+            })
+            .collect();
+        let spectrogram = bytes[spectrogram_start..]
+            .chunks_exact(SPECTROGRAM_BANDS)
+            .map(|chunk| {
+                let mut bands = [0; SPECTROGRAM_BANDS];
+                bands.copy_from_slice(chunk);
+                bands
+            })
+            .collect();
+
+        Some(Self { peaks, spectrogram })
+    }
+
+    fn write_cache(&self, path: &str, source_hash: u64) -> io::Result<()> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&source_hash.to_le_bytes());
+        bytes.extend_from_slice(&(self.peaks.len() as u32).to_le_bytes());
+        for (min, max) in self.peaks.iter() {
+            bytes.extend_from_slice(&min.to_bits().to_le_bytes());
+            bytes.extend_from_slice(&max.to_bits().to_le_bytes());
+        }
+        for bands in self.spectrogram.iter() {
+            bytes.extend_from_slice(bands);
+        }
+
+        fs::create_dir_all(CACHE_FOLDER)?;
+        fs::write(path, bytes)
+    }
+
+    /// Returns the lowest and highest samples between two times
+    fn peak_between(&self, start: f64, end: f64) -> Option<(f32, f32)> {
+        let first = (start * PEAKS_PER_SECOND).floor().max(0.) as usize;
+        let last = ((end * PEAKS_PER_SECOND).ceil().max(0.) as usize)
+            .max(first + 1)
+            .min(self.peaks.len());
+        if end < 0. || first >= last {
+            return None;
+        }
+
+        Some(
+            self.peaks[first..last]
+                .iter()
+                .fold((0f32, 0f32), |(min, max), peak| {
+                    (min.min(peak.0), max.max(peak.1))
+                }),
+        )
+    }
+
+    /// Returns the bands at a time
+    fn bands_at(&self, time: f64) -> Option<&[u8; SPECTROGRAM_BANDS]> {
+        if time < 0. {
+            return None;
+        }
+        self.spectrogram
+            .get((time * SPECTROGRAM_FRAMES_PER_SECOND) as usize)
+    }
+}
+
+/// Analysis of the song being edited, and the textures it's drawn into
+pub struct Waveform {
+    /// Audio file that was analysed
+    filename: Option<String>,
+    analysis: Option<SongAnalysis>,
+    show_spectrogram: bool,
+    waveform_texture: Handle<Texture>,
+    spectrogram_texture: Handle<Texture>,
+    waveform_material: Handle<ColorMaterial>,
+    spectrogram_material: Handle<ColorMaterial>,
+}
+impl FromWorld for Waveform {
+    fn from_world(world: &mut World) -> Self {
+        let world = world.cell();
+
+        let mut textures = world.get_resource_mut::<Assets<Texture>>().unwrap();
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+
+        let new_texture = |(width, height): (u32, u32)| {
+            Texture::new_fill(
+                Extent3d::new(width, height, 1),
+                TextureDimension::D2,
+                &[0, 0, 0, 0],
+                TextureFormat::Rgba8UnormSrgb,
+            )
+        };
+        let waveform_texture = textures.add(new_texture(WAVEFORM_SIZE));
+        let spectrogram_texture = textures.add(new_texture(SPECTROGRAM_SIZE));
+
+        Waveform {
+            filename: None,
+            analysis: None,
+            show_spectrogram: false,
+            waveform_material: materials.add(waveform_texture.clone().into()),
+            spectrogram_material: materials.add(spectrogram_texture.clone().into()),
+            waveform_texture,
+            spectrogram_texture,
+        }
+    }
+}
+
+/// Analyses the song once it's been decoded
+fn analyze_song(playback: Res<Playback>, mut waveform: ResMut<Waveform>) {
+    let song = match playback.song() {
+        Some(song) => song,
+        None => return,
+    };
+    if waveform.filename.as_deref() == Some(playback.filename()) {
+        return;
+    }
+
+    waveform.analysis = Some(SongAnalysis::load_cached(playback.filename(), song));
+    waveform.filename = Some(playback.filename().to_string());
+}
+
+struct WaveformSprite;
+struct SpectrogramSprite;
+
+fn setup_waveform(mut commands: Commands, waveform: Res<Waveform>) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            material: waveform.waveform_material.clone(),
+            sprite: Sprite::new(Vec2::new(800., WAVEFORM_SIZE.1 as f32)),
+            transform: Transform::from_translation(Vec3::new(0., WAVEFORM_Y, 0.5)),
+            visible: Visible {
+                is_transparent: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(WaveformSprite);
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            material: waveform.spectrogram_material.clone(),
+            sprite: Sprite::new(Vec2::new(800., 60.)),
+            transform: Transform::from_translation(Vec3::new(0., SPECTROGRAM_Y, 0.5)),
+            visible: Visible {
+                is_transparent: true,
+                is_visible: waveform.show_spectrogram,
+            },
+            ..Default::default()
+        })
+        .insert(SpectrogramSprite);
+}
+
+/// Shows or hides the spectrogram when Tab is pressed
+fn toggle_spectrogram(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut waveform: ResMut<Waveform>,
+    mut query: Query<&mut Visible, With<SpectrogramSprite>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        waveform.show_spectrogram = !waveform.show_spectrogram;
+        for mut visible in query.iter_mut() {
+            visible.is_visible = waveform.show_spectrogram;
+        }
+    }
+}
+
+/// Redraws the textures for the visible part of the timeline
+fn draw_waveform(
+    view: Res<TimelineView>,
+    waveform: Res<Waveform>,
+    mut textures: ResMut<Assets<Texture>>,
+) {
+    if !view.is_changed() && !waveform.is_changed() {
+        return;
+    }
+    let analysis = match &waveform.analysis {
+        Some(analysis) => analysis,
+        None => return,
+    };
+
+    // Each column of the texture covers the same width in the window
+    let column_time = |column: u32, width: u32| {
+        let x = -400. + 800. * column as f32 / width as f32;
+        (view.time_at(x), view.time_at(x + 800. / width as f32))
+    };
+
+    if let Some(texture) = textures.get_mut(&waveform.waveform_texture) {
+        let (width, height) = WAVEFORM_SIZE;
+        let mut data = vec![0; (width * height * 4) as usize];
+        for column in 0..width {
+            let (start, end) = column_time(column, width);
+            if let Some((min, max)) = analysis.peak_between(start, end) {
+                // Samples go from -1 at the bottom to 1 at the top
+                let row = |sample: f32| ((1. - sample) / 2. * (height - 1) as f32).round() as u32;
+                for row in row(max)..=row(min) {
+                    let index = ((row * width + column) * 4) as usize;
+                    data[index..index + 4].copy_from_slice(&[110, 170, 230, 255]);
+                }
+            }
+        }
+        texture.data = data;
+    }
+
+    if let Some(texture) = textures.get_mut(&waveform.spectrogram_texture) {
+        let (width, height) = SPECTROGRAM_SIZE;
+        let mut data = vec![0; (width * height * 4) as usize];
+        for column in 0..width {
+            let (start, end) = column_time(column, width);
+            if let Some(bands) = analysis.bands_at((start + end) / 2.) {
+                // Low frequencies go at the bottom
+                for (band, value) in bands.iter().enumerate() {
+                    let row = height - 1 - band as u32;
+                    let index = ((row * width + column) * 4) as usize;
+                    let color = [
+                        *value,
+                        (*value as u16 * *value as u16 / 255) as u8,
+                        80,
+                        *value,
+                    ];
+                    data[index..index + 4].copy_from_slice(&color);
+                }
+            }
+        }
+        texture.data = data;
+    }
+}
+
+fn despawn_waveform(
+    mut commands: Commands,
+    waveforms: Query<Entity, With<WaveformSprite>>,
+    spectrograms: Query<Entity, With<SpectrogramSprite>>,
+) {
+    for entity in waveforms.iter().chain(spectrograms.iter()) {
+        commands.entity(entity).despawn();
+    }
+}
+
+pub struct WaveformPlugin;
+impl Plugin for WaveformPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Waveform>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::EditMap).with_system(setup_waveform.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::EditMap)
+                    .with_system(analyze_song.system())
+                    .with_system(toggle_spectrogram.system())
+                    .with_system(draw_waveform.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::EditMap).with_system(despawn_waveform.system()),
+            );
+    }
+}